@@ -0,0 +1,189 @@
+use axum::{
+    extract::{rejection::JsonRejection, Request, State},
+    http::header,
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tracing::info;
+
+use crate::{
+    dashboard::Summary,
+    error::Error,
+    prover::{ProverHandle, ProverSettings},
+    rate_limit::{GroupStats, RouteGroup, SharedRateLimiter},
+    state::AppState,
+};
+
+/// Routes for runtime operations, mounted under `/admin` when an admin token is configured.
+///
+/// Every route requires an `Authorization: Bearer <token>` header.
+pub(crate) fn router(token: String) -> Router<AppState> {
+    Router::new()
+        .route("/state", get(dump_state))
+        .route("/settings", get(get_settings).patch(update_settings))
+        .route("/cleanup", post(cleanup))
+        .route("/queue/pause", post(pause))
+        .route("/queue/resume", post(resume))
+        .route("/queue/drain", post(drain))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_token,
+        ))
+}
+
+async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Err(Error::Unauthorized);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Compares two byte strings without leaking the position of the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// State summary returned by `GET /admin/state`.
+#[derive(Serialize)]
+pub(crate) struct AdminState {
+    #[serde(flatten)]
+    summary: Summary,
+    prover: ProverSettings,
+    /// Configured limit and request counters per route group
+    rate_limits: BTreeMap<RouteGroup, GroupStats>,
+}
+
+async fn dump_state(
+    State(s): State<AppState>,
+    Extension(prover_handle): Extension<ProverHandle>,
+    Extension(rate_limiter): Extension<SharedRateLimiter>,
+) -> Result<Json<AdminState>, Error> {
+    let state = s.read()?;
+    Ok(Json(AdminState {
+        summary: Summary::new(&state, prover_handle.sender.max_capacity()),
+        prover: prover_handle.control.settings(),
+        rate_limits: rate_limiter.stats(),
+    }))
+}
+
+/// Settings that can be changed at runtime.
+#[derive(Serialize)]
+pub(crate) struct Settings {
+    ttl_secs: u64,
+    #[serde(flatten)]
+    prover: ProverSettings,
+}
+
+/// Body of `PATCH /admin/settings`; omitted fields are left unchanged.
+#[derive(Deserialize)]
+pub(crate) struct SettingsUpdate {
+    /// New time-to-live, applied to existing entries as well
+    ttl_secs: Option<u64>,
+    /// New maximum number of concurrent sessions
+    workers: Option<usize>,
+}
+
+async fn get_settings(
+    State(s): State<AppState>,
+    Extension(prover_handle): Extension<ProverHandle>,
+) -> Result<Json<Settings>, Error> {
+    Ok(Json(Settings {
+        ttl_secs: s.read()?.ttl.as_secs(),
+        prover: prover_handle.control.settings(),
+    }))
+}
+
+async fn update_settings(
+    State(s): State<AppState>,
+    Extension(prover_handle): Extension<ProverHandle>,
+    request: Result<Json<SettingsUpdate>, JsonRejection>,
+) -> Result<Json<Settings>, Error> {
+    let Json(update) = request?;
+    if update.workers == Some(0) {
+        return Err(Error::InvalidRequest(
+            "workers must be at least 1; pause the queue instead".to_string(),
+        ));
+    }
+    if update.ttl_secs == Some(0) {
+        return Err(Error::InvalidRequest(
+            "ttl_secs must be at least 1; it would expire everything at once".to_string(),
+        ));
+    }
+    if let Some(ttl_secs) = update.ttl_secs {
+        s.write()?.ttl = Duration::from_secs(ttl_secs);
+        info!("TTL set to {ttl_secs}s");
+    }
+    if let Some(workers) = update.workers {
+        prover_handle
+            .control
+            .update(|settings| settings.workers = workers);
+        info!("Prover workers set to {workers}");
+    }
+    get_settings(State(s), Extension(prover_handle)).await
+}
+
+#[derive(Serialize)]
+pub(crate) struct CleanupRes {
+    evicted: usize,
+}
+
+async fn cleanup(State(s): State<AppState>) -> Result<Json<CleanupRes>, Error> {
+    let evicted = s.write()?.cleanup_expired();
+    info!("Cleaned up {evicted} expired entries on request");
+    Ok(Json(CleanupRes { evicted }))
+}
+
+async fn pause(Extension(prover_handle): Extension<ProverHandle>) -> Json<ProverSettings> {
+    prover_handle
+        .control
+        .update(|settings| settings.paused = true);
+    info!("Prover queue paused");
+    Json(prover_handle.control.settings())
+}
+
+async fn resume(Extension(prover_handle): Extension<ProverHandle>) -> Json<ProverSettings> {
+    prover_handle
+        .control
+        .update(|settings| settings.paused = false);
+    info!("Prover queue resumed");
+    Json(prover_handle.control.settings())
+}
+
+#[derive(Serialize)]
+pub(crate) struct DrainRes {
+    drained: usize,
+}
+
+/// Aborts all queued sessions; sessions already being proven are left to finish.
+async fn drain(State(s): State<AppState>) -> Result<Json<DrainRes>, Error> {
+    let drained = s.write()?.drain_queued();
+    info!("Drained {drained} queued sessions");
+    Ok(Json(DrainRes { drained }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
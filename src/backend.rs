@@ -0,0 +1,266 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use risc0_zkvm::{
+    get_prover_server, Executor, ExecutorEnv, ExecutorImpl, ExternalProver, Prover, ProverOpts,
+    Receipt, Session, SessionStats, VerifierContext,
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{field, info_span, Span};
+
+use crate::{
+    error::Error,
+    mock::{MockBackend, MockScript},
+    prover::ExecutorLimits,
+    session_log::{SessionLogWriter, SharedSessionLog},
+};
+
+/// Prover running the sessions of clients of the server's own zkVM version.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Backend {
+    /// Proves in-process, on the GPU when built with the `cuda` or `metal` feature
+    #[default]
+    Local,
+    /// Hands sessions to an `r0vm` binary of the same version over its IPC protocol
+    External { r0vm_path: PathBuf },
+    /// Skips the zkVM and plays out the script, for testing clients
+    Mock(MockScript),
+}
+
+impl Backend {
+    pub(crate) fn build(self) -> SharedBackend {
+        match self {
+            Backend::Local => Arc::new(LocalBackend),
+            Backend::External { r0vm_path } => Arc::new(ExternalBackend { r0vm_path }),
+            Backend::Mock(script) => Arc::new(MockBackend::new(script)),
+        }
+    }
+}
+
+/// A session ready to run.
+pub(crate) struct Job {
+    pub image_id: String,
+    pub elf: Vec<u8>,
+    pub input: Vec<u8>,
+    /// bincode encoded receipts of the assumptions
+    pub assumptions: Vec<Vec<u8>>,
+    pub limits: ExecutorLimits,
+    /// Receives the guest's stdout and stderr
+    pub log: SharedSessionLog,
+    /// Cancelled when the session is aborted; backends that can stop early do so
+    pub cancel: CancellationToken,
+    /// Set by backends that report it once the guest has been executed, so a session
+    /// failing or stopped while proving is still charged the cycles it used
+    pub executed: Arc<OnceLock<SessionStats>>,
+}
+
+/// The error a backend returns when it stops a job because of [`Job::cancel`].
+pub(crate) fn stopped() -> Error {
+    anyhow::anyhow!("session was stopped").into()
+}
+
+/// Builds the [`SessionStats`] of a backend that only counts segments and cycles.
+///
+/// risc0-zkvm marks the struct `#[non_exhaustive]`, so it has no struct literal
+/// outside that crate, but it deserializes from its fields.
+pub(crate) fn session_stats(
+    segments: usize,
+    total_cycles: u64,
+    user_cycles: u64,
+) -> Result<SessionStats, Error> {
+    Ok(serde_json::from_value(serde_json::json!({
+        "segments": segments,
+        "total_cycles": total_cycles,
+        "user_cycles": user_cycles,
+        "paging_cycles": 0,
+        "reserved_cycles": 0,
+    }))?)
+}
+
+/// The outcome of [`ProverBackend::prove`].
+pub(crate) struct Proof {
+    /// bincode encoded composite receipt
+    pub receipt: Vec<u8>,
+    /// `None` if the backend does not report them
+    pub stats: Option<SessionStats>,
+}
+
+/// The stages of a session: executing the guest, proving it to a composite receipt,
+/// compressing that to a succinct receipt and finally to a Groth16 one.
+///
+/// Receipts are passed bincode encoded, so backends of other zkVM versions can hand
+/// theirs through undecoded. Work handed to a blocking thread cannot be interrupted:
+/// its future only returns once the work is done, even after the job is cancelled.
+pub(crate) trait ProverBackend: Send + Sync {
+    /// Executes the guest without proving it.
+    fn execute(&self, job: Job) -> BoxFuture<'_, Result<SessionStats, Error>>;
+
+    /// Executes the guest and proves it to a composite receipt.
+    fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>>;
+
+    /// Compresses a composite receipt to a succinct one.
+    fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>>;
+
+    /// Compresses a succinct receipt to a Groth16 one.
+    fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>>;
+}
+
+pub(crate) type SharedBackend = Arc<dyn ProverBackend>;
+
+/// Runs `f` on a blocking thread within the current span, keeping the CPU bound
+/// zkVM off the async runtime.
+async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let span = Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f)).await?
+}
+
+fn executor_env(job: &Job) -> Result<ExecutorEnv<'static>, Error> {
+    let mut env = ExecutorEnv::builder();
+    for receipt in &job.assumptions {
+        let receipt: Receipt = bincode::deserialize(receipt)?;
+        env.add_assumption(receipt);
+    }
+    let log = SessionLogWriter(Arc::clone(&job.log));
+    env.write_slice(&job.input)
+        .session_limit(job.limits.max_cycles)
+        .segment_limit_po2(job.limits.segment_limit_po2)
+        .stdout(log.clone())
+        .stderr(log)
+        .build()
+        .map_err(|e| anyhow::anyhow!("failed to build executor environment: {:?}", e).into())
+}
+
+/// Decodes `receipt`, compresses it with `compress` and encodes the result.
+fn compress_with(
+    receipt: &[u8],
+    compress: impl FnOnce(&Receipt) -> anyhow::Result<Receipt>,
+) -> Result<Vec<u8>, Error> {
+    let receipt: Receipt = bincode::deserialize(receipt)?;
+    Ok(bincode::serialize(&compress(&receipt)?)?)
+}
+
+/// Proves in-process with the zkVM the server is built with.
+pub(crate) struct LocalBackend;
+
+impl LocalBackend {
+    /// Runs the executor in an `execute` span, which receives the cycle and segment counts.
+    fn run_executor(job: &Job) -> Result<Session, Error> {
+        let env = executor_env(job)?;
+        let span = info_span!(
+            "execute",
+            user_cycles = field::Empty,
+            total_cycles = field::Empty,
+            segments = field::Empty,
+        );
+        let _enter = span.enter();
+        let session = ExecutorImpl::from_elf(env, &job.elf)?.run()?;
+        span.record("user_cycles", session.user_cycles);
+        span.record("total_cycles", session.total_cycles);
+        span.record("segments", session.segments.len());
+        let _ = job.executed.set(session.stats());
+        Ok(session)
+    }
+
+    fn compress_to(
+        opts: ProverOpts,
+        receipt: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, Error>> {
+        blocking(move || {
+            let prover = get_prover_server(&opts)?;
+            compress_with(&receipt, |receipt| prover.compress(&opts, receipt))
+        })
+        .boxed()
+    }
+}
+
+impl ProverBackend for LocalBackend {
+    fn execute(&self, job: Job) -> BoxFuture<'_, Result<SessionStats, Error>> {
+        blocking(move || Ok(Self::run_executor(&job)?.stats())).boxed()
+    }
+
+    fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>> {
+        blocking(move || {
+            let session = Self::run_executor(&job)?;
+            let prover = get_prover_server(&ProverOpts::composite())?;
+            let info = info_span!("prove_segments", segments = session.segments.len())
+                .in_scope(|| prover.prove_session(&VerifierContext::default(), &session))?;
+            Ok(Proof {
+                receipt: bincode::serialize(&info.receipt)?,
+                stats: Some(info.stats),
+            })
+        })
+        .boxed()
+    }
+
+    fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        Self::compress_to(ProverOpts::succinct(), receipt)
+    }
+
+    fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        Self::compress_to(ProverOpts::groth16(), receipt)
+    }
+}
+
+/// Hands sessions to an `r0vm` binary of the server's own zkVM version, which runs
+/// each stage in a subprocess driven over IPC.
+pub(crate) struct ExternalBackend {
+    r0vm_path: PathBuf,
+}
+
+impl ExternalBackend {
+    fn compress_to(
+        &self,
+        opts: ProverOpts,
+        receipt: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, Error>> {
+        let prover = ExternalProver::new("bonsai", &self.r0vm_path);
+        blocking(move || compress_with(&receipt, |receipt| prover.compress(&opts, receipt))).boxed()
+    }
+}
+
+impl ProverBackend for ExternalBackend {
+    fn execute(&self, job: Job) -> BoxFuture<'_, Result<SessionStats, Error>> {
+        let prover = ExternalProver::new("bonsai", &self.r0vm_path);
+        blocking(move || {
+            let info = prover.execute(executor_env(&job)?, &job.elf)?;
+            // r0vm only reports the size and cycles of each segment
+            session_stats(
+                info.segments.len(),
+                info.segments.iter().map(|s| 1u64 << s.po2).sum(),
+                info.segments.iter().map(|s| u64::from(s.cycles)).sum(),
+            )
+        })
+        .boxed()
+    }
+
+    fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>> {
+        let prover = ExternalProver::new("bonsai", &self.r0vm_path);
+        blocking(move || {
+            let info = prover.prove_with_ctx(
+                executor_env(&job)?,
+                &VerifierContext::default(),
+                &job.elf,
+                &ProverOpts::composite(),
+            )?;
+            Ok(Proof {
+                receipt: bincode::serialize(&info.receipt)?,
+                stats: Some(info.stats),
+            })
+        })
+        .boxed()
+    }
+
+    fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        self.compress_to(ProverOpts::succinct(), receipt)
+    }
+
+    fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        self.compress_to(ProverOpts::groth16(), receipt)
+    }
+}
@@ -0,0 +1,295 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use std::{io, io::SeekFrom, ops::Range, path::PathBuf, sync::Arc};
+use tempfile::TempPath;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::error::Error;
+
+/// Limits applied to uploaded request bodies.
+#[derive(Debug, Clone)]
+pub(crate) struct BlobOptions {
+    /// Maximum accepted body size in bytes; larger uploads are rejected with 413
+    pub max_body_size: usize,
+    /// Bodies larger than this are written to a temporary file instead of kept in memory
+    pub spill_threshold: usize,
+    /// Directory in which spilled bodies are stored
+    pub spill_dir: PathBuf,
+}
+
+/// A stored payload (image, input or receipt), either held in memory or spilled to disk.
+///
+/// Cloning is cheap: in-memory data is reference counted and spilled files are
+/// removed once the last clone is dropped.
+#[derive(Debug, Clone)]
+pub(crate) enum Blob {
+    Memory(Bytes),
+    File { path: Arc<TempPath>, len: u64 },
+}
+
+impl Blob {
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            Blob::Memory(bytes) => bytes.len() as u64,
+            Blob::File { len, .. } => *len,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn is_on_disk(&self) -> bool {
+        matches!(self, Blob::File { .. })
+    }
+
+    /// Reads a request body chunk by chunk, spilling to a temporary file once it
+    /// grows past `options.spill_threshold`.
+    pub(crate) async fn from_body(body: Body, options: &BlobOptions) -> Result<Self, Error> {
+        let mut stream = body.into_data_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut spilled: Option<(File, TempPath)> = None;
+        let mut len: usize = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            len += chunk.len();
+            if len > options.max_body_size {
+                return Err(Error::PayloadTooLarge);
+            }
+
+            if let Some((file, _)) = spilled.as_mut() {
+                file.write_all(&chunk).await?;
+            } else if len > options.spill_threshold {
+                let (file, path) = tempfile::Builder::new()
+                    .prefix("bonsai-blob-")
+                    .tempfile_in(&options.spill_dir)?
+                    .into_parts();
+                let mut file = File::from_std(file);
+                file.write_all(&buffer).await?;
+                file.write_all(&chunk).await?;
+                buffer = Vec::new();
+                spilled = Some((file, path));
+            } else {
+                buffer.extend_from_slice(&chunk);
+            }
+        }
+
+        match spilled {
+            Some((mut file, path)) => {
+                file.flush().await?;
+                Ok(Blob::File {
+                    path: Arc::new(path),
+                    len: len as u64,
+                })
+            }
+            None => Ok(Blob::Memory(Bytes::from(buffer))),
+        }
+    }
+
+    /// Loads the full payload into memory.
+    pub(crate) async fn read_all(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Blob::Memory(bytes) => Ok(bytes.to_vec()),
+            Blob::File { path, .. } => tokio::fs::read(path.as_ref()).await,
+        }
+    }
+
+    /// Returns a streaming body over the given byte range.
+    pub(crate) async fn into_body(self, range: Range<u64>) -> io::Result<Body> {
+        match self {
+            Blob::Memory(bytes) => Ok(Body::from(
+                bytes.slice(range.start as usize..range.end as usize),
+            )),
+            Blob::File { path, .. } => {
+                let mut file = File::open(path.as_ref()).await?;
+                file.seek(SeekFrom::Start(range.start)).await?;
+                let reader = file.take(range.end - range.start);
+                // keep the temporary file alive until the response has been streamed
+                let stream = ReaderStream::new(reader).map(move |chunk| {
+                    let _ = &path;
+                    chunk
+                });
+                Ok(Body::from_stream(stream))
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(data: Vec<u8>) -> Self {
+        Blob::Memory(Bytes::from(data))
+    }
+}
+
+/// Builds a download response for `blob`, honoring a single-range `Range` header.
+pub(crate) async fn blob_response(blob: Blob, headers: &HeaderMap) -> Result<Response, Error> {
+    let len = blob.len();
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) => match parse_range(value, len) {
+            Ok(range) => range,
+            Err(RangeNotSatisfiable) => {
+                return Ok((
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+                )
+                    .into_response());
+            }
+        },
+        None => None,
+    };
+
+    let (status, range) = match range {
+        Some(range) => (StatusCode::PARTIAL_CONTENT, range),
+        None => (StatusCode::OK, 0..len),
+    };
+    let content_length = range.end - range.start;
+    let content_range = format!(
+        "bytes {}-{}/{len}",
+        range.start,
+        range.end.saturating_sub(1)
+    );
+    let body = blob.into_body(range).await?;
+
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+    if status == StatusCode::PARTIAL_CONTENT {
+        headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).map_err(anyhow::Error::from)?,
+        );
+    }
+    Ok(response)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RangeNotSatisfiable;
+
+/// Parses a `Range` header value against a payload of `len` bytes.
+///
+/// Only a single `bytes` range is supported. Malformed values and multi-range
+/// requests are ignored (`Ok(None)`), in which case the full payload is served,
+/// as permitted by RFC 9110.
+pub(crate) fn parse_range(
+    value: &str,
+    len: u64,
+) -> Result<Option<Range<u64>>, RangeNotSatisfiable> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // suffix range: the last N bytes
+        let Ok(suffix) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if suffix == 0 || len == 0 {
+            return Err(RangeNotSatisfiable);
+        }
+        return Ok(Some(len.saturating_sub(suffix)..len));
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return Ok(None);
+    };
+    let end = if end.is_empty() {
+        None
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => Some(end),
+            _ => return Ok(None),
+        }
+    };
+    if start >= len {
+        return Err(RangeNotSatisfiable);
+    }
+    let end = end.map_or(len, |end| end.saturating_add(1).min(len));
+    Ok(Some(start..end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(spill_threshold: usize) -> BlobOptions {
+        BlobOptions {
+            max_body_size: 64,
+            spill_threshold,
+            spill_dir: std::env::temp_dir(),
+        }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Ok(Some(0..10)));
+        assert_eq!(parse_range("bytes=10-", 100), Ok(Some(10..100)));
+        assert_eq!(parse_range("bytes=-10", 100), Ok(Some(90..100)));
+        assert_eq!(parse_range("bytes=90-200", 100), Ok(Some(90..100)));
+        assert_eq!(parse_range("bytes=-200", 100), Ok(Some(0..100)));
+    }
+
+    #[test]
+    fn test_parse_range_ignored() {
+        assert_eq!(parse_range("items=0-9", 100), Ok(None));
+        assert_eq!(parse_range("bytes=0-9,20-29", 100), Ok(None));
+        assert_eq!(parse_range("bytes=9-0", 100), Ok(None));
+        assert_eq!(parse_range("bytes=abc", 100), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_range_not_satisfiable() {
+        assert_eq!(parse_range("bytes=100-", 100), Err(RangeNotSatisfiable));
+        assert_eq!(parse_range("bytes=-0", 100), Err(RangeNotSatisfiable));
+        assert_eq!(parse_range("bytes=0-", 0), Err(RangeNotSatisfiable));
+    }
+
+    #[tokio::test]
+    async fn test_from_body_in_memory() {
+        let blob = Blob::from_body(Body::from(vec![1u8; 8]), &options(16))
+            .await
+            .unwrap();
+        assert!(matches!(blob, Blob::Memory(_)));
+        assert_eq!(blob.read_all().await.unwrap(), vec![1u8; 8]);
+    }
+
+    #[tokio::test]
+    async fn test_from_body_spills_to_disk() {
+        let data: Vec<u8> = (0..32).collect();
+        let blob = Blob::from_body(Body::from(data.clone()), &options(16))
+            .await
+            .unwrap();
+        assert!(matches!(blob, Blob::File { len: 32, .. }));
+        assert_eq!(blob.read_all().await.unwrap(), data);
+
+        let body = blob.into_body(4..8).await.unwrap();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes.as_ref(), &data[4..8]);
+    }
+
+    #[tokio::test]
+    async fn test_from_body_too_large() {
+        let result = Blob::from_body(Body::from(vec![0u8; 65]), &options(16)).await;
+        assert!(matches!(result, Err(Error::PayloadTooLarge)));
+    }
+}
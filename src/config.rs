@@ -0,0 +1,624 @@
+use anyhow::{bail, Context};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::{fmt::Display, path::Path, path::PathBuf, str::FromStr, time::Duration};
+use url::Url;
+
+use crate::{
+    listener::UNIX_PREFIX,
+    prover::{MAX_SEGMENT_LIMIT_PO2, MIN_SEGMENT_LIMIT_PO2},
+    url_resolver::normalize_base_path,
+    Backend, MockScript, Quotas, RateLimits, ServerOptions, TlsOptions, TrustedProxies,
+};
+
+/// Prefix of the environment variables overriding configuration values,
+/// e.g. `BONSAI_TTL` or `BONSAI_OTEL_ENABLE`.
+pub const ENV_PREFIX: &str = "BONSAI_";
+
+pub const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Server configuration, read from a TOML or YAML file, `BONSAI_*` environment
+/// variables and command line arguments, in increasing order of precedence.
+///
+/// Every field is optional; unset fields fall back to their defaults.
+#[derive(Args, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Server URL (must be http:// or https://)
+    #[arg(long)]
+    pub server_url: Option<Url>,
+
+    /// Address to listen on (e.g., "127.0.0.1:8080", "0.0.0.0:8080", "unix:/run/bonsai.sock") (default: 127.0.0.1:8080)
+    #[arg(long, value_name = "ADDRESS")]
+    pub listen_address: Option<String>,
+
+    /// Time-to-live for cached entries in seconds (default: 14400 = 4 hours)
+    #[arg(long, value_name = "SECONDS")]
+    pub ttl: Option<u64>,
+
+    /// Channel buffer size for prover queue (default: 8)
+    #[arg(long, value_name = "SIZE")]
+    pub channel_buffer_size: Option<usize>,
+
+    /// Cleanup interval in seconds (default: 60)
+    #[arg(long, value_name = "SECONDS")]
+    pub cleanup_interval: Option<u64>,
+
+    /// Maximum size of an uploaded image, input or receipt in bytes (default: 268435456 = 256 MiB)
+    #[arg(long, value_name = "BYTES")]
+    pub max_body_size: Option<usize>,
+
+    /// Uploads larger than this are spilled to disk instead of kept in memory (default: 16777216 = 16 MiB)
+    #[arg(long, value_name = "BYTES")]
+    pub spill_threshold: Option<usize>,
+
+    /// Directory for spilled uploads (default: system temp directory)
+    #[arg(long, value_name = "DIR")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Default segment size as a power of two (accepted range: 13-24, default: 20)
+    #[arg(long, value_name = "PO2")]
+    pub segment_limit_po2: Option<u32>,
+
+    /// Maximum number of executor cycles per session (default: unlimited)
+    #[arg(long, value_name = "CYCLES")]
+    pub max_cycles: Option<u64>,
+
+    /// Maximum wall-clock time per session in seconds (default: unlimited)
+    #[arg(long, value_name = "SECONDS")]
+    pub session_timeout: Option<u64>,
+
+    /// Maximum bytes of guest stdout/stderr kept per session (default: 1048576 = 1 MiB)
+    #[arg(long, value_name = "BYTES")]
+    pub session_log_capacity: Option<usize>,
+
+    /// Maximum number of sessions proven concurrently (default: 1)
+    #[arg(long, value_name = "COUNT")]
+    pub workers: Option<usize>,
+
+    /// Bearer token enabling the /admin API (default: admin API disabled)
+    #[arg(long, value_name = "TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Time running sessions may take to finish after SIGTERM/SIGINT (default: 300)
+    #[arg(long, value_name = "SECONDS")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// PEM certificate chain; serves HTTPS instead of HTTP when set together with --tls-key
+    #[arg(long, value_name = "FILE")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, value_name = "FILE")]
+    pub tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates; when set, clients must present a certificate signed by one of them
+    #[arg(long, value_name = "FILE")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Comma-separated IPs/CIDRs, or `unix`, whose Forwarded/X-Forwarded-* headers are honored (default: 127.0.0.0/8,::1/128,unix)
+    #[arg(long, value_name = "CIDRS")]
+    pub trusted_proxies: Option<TrustedProxies>,
+
+    /// Path prefix to serve the API under, e.g. "/bonsai" (default: served at the root)
+    #[arg(long, value_name = "PATH")]
+    pub base_path: Option<String>,
+
+    /// rzup installation (e.g. ~/.risc0) whose r0vm binaries serve clients of other zkVM versions (default: built-in version only)
+    #[arg(long, value_name = "DIR")]
+    pub risc0_home: Option<PathBuf>,
+
+    /// Prover running sessions: local (in-process), external (r0vm over IPC) or mock (no proving, for testing clients) (default: local)
+    #[arg(long, value_name = "BACKEND")]
+    pub backend: Option<BackendKind>,
+
+    /// r0vm binary for the external backend (default: r0vm in PATH)
+    #[arg(long, value_name = "FILE")]
+    pub r0vm_path: Option<PathBuf>,
+
+    /// Required r0vm version (format: <major>.<minor>, e.g., "1.0", "1.2")
+    #[arg(long, value_name = "VERSION")]
+    pub r0vm_version: Option<String>,
+
+    /// Export traces over OTLP (file or `BONSAI_OTEL_ENABLE` only)
+    #[arg(skip)]
+    pub otel_enable: Option<bool>,
+
+    /// Per API key limits on sessions, cycles and storage (file only)
+    #[arg(skip)]
+    pub quotas: Option<Quotas>,
+
+    /// Request rate limits per route group and API key or client IP (file only)
+    #[arg(skip)]
+    pub rate_limits: Option<RateLimits>,
+
+    /// Scripted session outcomes of the mock backend (file only)
+    #[arg(skip)]
+    pub mock: Option<MockScript>,
+}
+
+impl Config {
+    /// Builds the configuration from an optional file, the `BONSAI_*` environment
+    /// variables and the command line arguments in `cli`.
+    pub fn load(path: Option<&Path>, cli: Config) -> anyhow::Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env(std::env::vars())?;
+        Ok(config.merge(cli))
+    }
+
+    /// Reads a TOML (`.toml`) or YAML (`.yaml`, `.yml`) configuration file.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(anyhow::Error::from),
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(anyhow::Error::from),
+            _ => bail!(
+                "unsupported config file {}: expected a .toml, .yaml or .yml extension",
+                path.display()
+            ),
+        };
+        config.with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Overrides values with `BONSAI_<FIELD>` variables, e.g. `BONSAI_LISTEN_ADDRESS`.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<()> {
+        for (key, value) in vars {
+            let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            match name {
+                "SERVER_URL" => self.server_url = Some(parse_env(&key, &value)?),
+                "LISTEN_ADDRESS" => self.listen_address = Some(value),
+                "TTL" => self.ttl = Some(parse_env(&key, &value)?),
+                "CHANNEL_BUFFER_SIZE" => self.channel_buffer_size = Some(parse_env(&key, &value)?),
+                "CLEANUP_INTERVAL" => self.cleanup_interval = Some(parse_env(&key, &value)?),
+                "MAX_BODY_SIZE" => self.max_body_size = Some(parse_env(&key, &value)?),
+                "SPILL_THRESHOLD" => self.spill_threshold = Some(parse_env(&key, &value)?),
+                "SPILL_DIR" => self.spill_dir = Some(PathBuf::from(value)),
+                "SEGMENT_LIMIT_PO2" => self.segment_limit_po2 = Some(parse_env(&key, &value)?),
+                "MAX_CYCLES" => self.max_cycles = Some(parse_env(&key, &value)?),
+                "SESSION_TIMEOUT" => self.session_timeout = Some(parse_env(&key, &value)?),
+                "SESSION_LOG_CAPACITY" => {
+                    self.session_log_capacity = Some(parse_env(&key, &value)?)
+                }
+                "WORKERS" => self.workers = Some(parse_env(&key, &value)?),
+                "ADMIN_TOKEN" => self.admin_token = Some(value),
+                "SHUTDOWN_TIMEOUT" => self.shutdown_timeout = Some(parse_env(&key, &value)?),
+                "TLS_CERT" => self.tls_cert = Some(PathBuf::from(value)),
+                "TLS_KEY" => self.tls_key = Some(PathBuf::from(value)),
+                "TLS_CLIENT_CA" => self.tls_client_ca = Some(PathBuf::from(value)),
+                "TRUSTED_PROXIES" => self.trusted_proxies = Some(parse_env(&key, &value)?),
+                "BASE_PATH" => self.base_path = Some(value),
+                "RISC0_HOME" => self.risc0_home = Some(PathBuf::from(value)),
+                "BACKEND" => self.backend = Some(parse_env(&key, &value)?),
+                "R0VM_PATH" => self.r0vm_path = Some(PathBuf::from(value)),
+                "R0VM_VERSION" => self.r0vm_version = Some(value),
+                "OTEL_ENABLE" => {
+                    self.otel_enable = Some(parse_env(&key, &value.to_ascii_lowercase())?)
+                }
+                // other variables share the prefix, e.g. BONSAI_API_KEY read by the SDK
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `self` with every value set in `overrides` replaced.
+    pub fn merge(self, overrides: Config) -> Self {
+        Self {
+            server_url: overrides.server_url.or(self.server_url),
+            listen_address: overrides.listen_address.or(self.listen_address),
+            ttl: overrides.ttl.or(self.ttl),
+            channel_buffer_size: overrides.channel_buffer_size.or(self.channel_buffer_size),
+            cleanup_interval: overrides.cleanup_interval.or(self.cleanup_interval),
+            max_body_size: overrides.max_body_size.or(self.max_body_size),
+            spill_threshold: overrides.spill_threshold.or(self.spill_threshold),
+            spill_dir: overrides.spill_dir.or(self.spill_dir),
+            segment_limit_po2: overrides.segment_limit_po2.or(self.segment_limit_po2),
+            max_cycles: overrides.max_cycles.or(self.max_cycles),
+            session_timeout: overrides.session_timeout.or(self.session_timeout),
+            session_log_capacity: overrides.session_log_capacity.or(self.session_log_capacity),
+            workers: overrides.workers.or(self.workers),
+            admin_token: overrides.admin_token.or(self.admin_token),
+            shutdown_timeout: overrides.shutdown_timeout.or(self.shutdown_timeout),
+            tls_cert: overrides.tls_cert.or(self.tls_cert),
+            tls_key: overrides.tls_key.or(self.tls_key),
+            tls_client_ca: overrides.tls_client_ca.or(self.tls_client_ca),
+            trusted_proxies: overrides.trusted_proxies.or(self.trusted_proxies),
+            base_path: overrides.base_path.or(self.base_path),
+            risc0_home: overrides.risc0_home.or(self.risc0_home),
+            backend: overrides.backend.or(self.backend),
+            r0vm_path: overrides.r0vm_path.or(self.r0vm_path),
+            r0vm_version: overrides.r0vm_version.or(self.r0vm_version),
+            otel_enable: overrides.otel_enable.or(self.otel_enable),
+            quotas: overrides.quotas.or(self.quotas),
+            rate_limits: overrides.rate_limits.or(self.rate_limits),
+            mock: overrides.mock.or(self.mock),
+        }
+    }
+
+    pub fn listen_address(&self) -> &str {
+        self.listen_address
+            .as_deref()
+            .unwrap_or(DEFAULT_LISTEN_ADDRESS)
+    }
+
+    pub fn otel_enable(&self) -> bool {
+        self.otel_enable.unwrap_or(false)
+    }
+
+    /// Checks all values, reporting every invalid one at once.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut errors = vec![];
+        if let Some(url) = &self.server_url {
+            if !matches!(url.scheme(), "http" | "https") {
+                errors.push(format!(
+                    "server_url must use http:// or https:// scheme, got {url}"
+                ));
+            }
+        }
+        for (name, is_zero) in [
+            ("ttl", self.ttl == Some(0)),
+            ("cleanup_interval", self.cleanup_interval == Some(0)),
+            ("channel_buffer_size", self.channel_buffer_size == Some(0)),
+            ("max_body_size", self.max_body_size == Some(0)),
+            ("workers", self.workers == Some(0)),
+        ] {
+            if is_zero {
+                errors.push(format!("{name} must be greater than 0"));
+            }
+        }
+        if let Some(po2) = self.segment_limit_po2 {
+            if !(MIN_SEGMENT_LIMIT_PO2..=MAX_SEGMENT_LIMIT_PO2).contains(&po2) {
+                errors.push(format!(
+                    "segment_limit_po2 must be between {MIN_SEGMENT_LIMIT_PO2} and {MAX_SEGMENT_LIMIT_PO2}, got {po2}"
+                ));
+            }
+        }
+        for (name, dir) in [
+            ("spill_dir", &self.spill_dir),
+            ("risc0_home", &self.risc0_home),
+        ] {
+            if let Some(dir) = dir.as_ref().filter(|dir| !dir.is_dir()) {
+                errors.push(format!(
+                    "{name} {} is not an existing directory",
+                    dir.display()
+                ));
+            }
+        }
+        if let Some(Err(err)) = self.base_path.as_deref().map(normalize_base_path) {
+            errors.push(err);
+        }
+        if let Some(Err(err)) = self.rate_limits.as_ref().map(RateLimits::validate) {
+            errors.push(err);
+        }
+        if self.r0vm_path.is_some() && self.backend != Some(BackendKind::External) {
+            errors.push("r0vm_path requires the external backend".to_string());
+        }
+        if let Some(mock) = &self.mock {
+            if self.backend != Some(BackendKind::Mock) {
+                errors.push("mock requires the mock backend".to_string());
+            }
+            if let Err(err) = mock.validate() {
+                errors.push(err);
+            }
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => errors.push("tls_cert requires tls_key".to_string()),
+            (None, Some(_)) => errors.push("tls_key requires tls_cert".to_string()),
+            (None, None) if self.tls_client_ca.is_some() => {
+                errors.push("tls_client_ca requires tls_cert and tls_key".to_string())
+            }
+            _ => {}
+        }
+        if let Some(path) = self.listen_address().strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                errors.push("listen_address unix: requires a socket path".to_string());
+            }
+            if self.tls_cert.is_some() {
+                errors.push("TLS is not supported on Unix domain sockets".to_string());
+            }
+        }
+        for (name, path) in [
+            ("tls_cert", &self.tls_cert),
+            ("tls_key", &self.tls_key),
+            ("tls_client_ca", &self.tls_client_ca),
+        ] {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                errors.push(format!("{name} {} is not an existing file", path.display()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            bail!("invalid configuration:\n  - {}", errors.join("\n  - "))
+        }
+    }
+
+    /// Validates the configuration and fills in defaults for unset values.
+    pub fn server_options(&self) -> anyhow::Result<ServerOptions> {
+        self.validate()?;
+        Ok(ServerOptions {
+            server_url: self.server_url.clone(),
+            ttl: Duration::from_secs(self.ttl.unwrap_or(14400)),
+            channel_buffer_size: self.channel_buffer_size.unwrap_or(8),
+            cleanup_interval: Duration::from_secs(self.cleanup_interval.unwrap_or(60)),
+            max_body_size: self.max_body_size.unwrap_or(256 * 1024 * 1024),
+            spill_threshold: self.spill_threshold.unwrap_or(16 * 1024 * 1024),
+            spill_dir: self.spill_dir.clone(),
+            segment_limit_po2: self.segment_limit_po2.unwrap_or(20),
+            max_cycles: self.max_cycles,
+            session_timeout: self.session_timeout.map(Duration::from_secs),
+            session_log_capacity: self.session_log_capacity.unwrap_or(1024 * 1024),
+            workers: self.workers.unwrap_or(1),
+            admin_token: self.admin_token.clone(),
+            shutdown_timeout: Duration::from_secs(self.shutdown_timeout.unwrap_or(300)),
+            tls: self
+                .tls_cert
+                .clone()
+                .zip(self.tls_key.clone())
+                .map(|(cert_path, key_path)| TlsOptions {
+                    cert_path,
+                    key_path,
+                    client_ca_path: self.tls_client_ca.clone(),
+                }),
+            trusted_proxies: self.trusted_proxies.clone().unwrap_or_default(),
+            base_path: self.base_path.clone(),
+            quotas: self.quotas.clone().unwrap_or_default(),
+            rate_limits: self.rate_limits.clone().unwrap_or_default(),
+            risc0_home: self.risc0_home.clone(),
+            backend: match self.backend.unwrap_or_default() {
+                BackendKind::Local => Backend::Local,
+                BackendKind::External => Backend::External {
+                    r0vm_path: self.r0vm_path.clone().unwrap_or_else(|| "r0vm".into()),
+                },
+                BackendKind::Mock => Backend::Mock(self.mock.clone().unwrap_or_default()),
+            },
+        })
+    }
+}
+
+/// Prover backend selected by name, see [`Backend`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Local,
+    External,
+    Mock,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true)
+    }
+}
+
+fn parse_env<T>(key: &str, value: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid value {value:?} for {key}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write_config(extension: &str, contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new()
+            .suffix(extension)
+            .tempfile()
+            .unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_from_toml_file() {
+        let file = write_config(
+            ".toml",
+            r#"
+            server_url = "http://localhost:8080"
+            listen_address = "0.0.0.0:8080"
+            ttl = 600
+            workers = 2
+            trusted_proxies = ["10.0.0.0/8", "unix"]
+
+            [quotas.default]
+            concurrent_sessions = 2
+
+            [quotas.keys.ci]
+            stored_bytes = 1024
+            "#,
+        );
+        let config = Config::from_file(file.path()).unwrap();
+        assert_eq!(config.listen_address(), "0.0.0.0:8080");
+        assert_eq!(config.ttl, Some(600));
+        assert_eq!(config.workers, Some(2));
+
+        let options = config.server_options().unwrap();
+        assert_eq!(options.ttl, Duration::from_secs(600));
+        assert_eq!(options.channel_buffer_size, 8);
+        assert_eq!(options.trusted_proxies.to_string(), "10.0.0.0/8,unix");
+        let quota = options.quotas.for_tenant(Some("ci"));
+        assert_eq!(quota.concurrent_sessions, Some(2));
+        assert_eq!(quota.stored_bytes, Some(1024));
+    }
+
+    #[test]
+    fn test_from_yaml_file() {
+        let file = write_config(
+            ".yaml",
+            "admin_token: secret\nsession_timeout: 30\notel_enable: true\n",
+        );
+        let config = Config::from_file(file.path()).unwrap();
+        assert_eq!(config.admin_token.as_deref(), Some("secret"));
+        assert!(config.otel_enable());
+        assert_eq!(
+            config.server_options().unwrap().session_timeout,
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_from_file_rejects_unknown_fields_and_extensions() {
+        let file = write_config(".toml", "tll = 600\n");
+        assert!(Config::from_file(file.path()).is_err());
+
+        let file = write_config(".json", "{}");
+        assert!(Config::from_file(file.path()).is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let mut config = Config {
+            ttl: Some(600),
+            workers: Some(2),
+            channel_buffer_size: Some(4),
+            ..Default::default()
+        };
+        config
+            .apply_env(env(&[
+                ("BONSAI_TTL", "900"),
+                ("BONSAI_WORKERS", "3"),
+                ("BONSAI_API_KEY", "ignored"),
+                ("BONSAI_TRUSTED_PROXIES", ""),
+                ("TTL", "1"),
+            ]))
+            .unwrap();
+        let config = config.merge(Config {
+            workers: Some(4),
+            ..Default::default()
+        });
+
+        assert_eq!(config.channel_buffer_size, Some(4)); // file
+        assert_eq!(config.ttl, Some(900)); // environment
+        assert_eq!(config.workers, Some(4)); // command line
+        assert_eq!(config.trusted_proxies, Some(TrustedProxies::none()));
+    }
+
+    #[test]
+    fn test_backend() {
+        let mut config = Config::default();
+        config
+            .apply_env(env(&[
+                ("BONSAI_BACKEND", "External"),
+                ("BONSAI_R0VM_PATH", "/opt/r0vm"),
+            ]))
+            .unwrap();
+        assert_eq!(
+            config.server_options().unwrap().backend,
+            Backend::External {
+                r0vm_path: PathBuf::from("/opt/r0vm")
+            }
+        );
+
+        let config = config.merge(Config {
+            backend: Some(BackendKind::Mock),
+            ..Default::default()
+        });
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("r0vm_path"), "{err}");
+        assert!(Config::default()
+            .apply_env(env(&[("BONSAI_BACKEND", "gpu")]))
+            .is_err());
+
+        let file = write_config(
+            ".toml",
+            "backend = \"mock\"\n[[mock.rules]]\nsessions = 1\nerror_msg = \"boom\"\n",
+        );
+        let mut config = Config::from_file(file.path()).unwrap();
+        let Backend::Mock(script) = config.server_options().unwrap().backend else {
+            panic!("expected the mock backend");
+        };
+        assert_eq!(script.rules[0].error_msg.as_deref(), Some("boom"));
+        config.backend = None;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("mock requires"), "{err}");
+    }
+
+    #[test]
+    fn test_invalid_env_value() {
+        let err = Config::default()
+            .apply_env(env(&[("BONSAI_TTL", "forever")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("BONSAI_TTL"));
+
+        let mut config = Config::default();
+        config
+            .apply_env(env(&[("BONSAI_OTEL_ENABLE", "TRUE")]))
+            .unwrap();
+        assert!(config.otel_enable());
+        let err = Config::default()
+            .apply_env(env(&[("BONSAI_OTEL_ENABLE", "yes")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("BONSAI_OTEL_ENABLE"));
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let config = Config {
+            server_url: Some(Url::parse("ftp://localhost").unwrap()),
+            workers: Some(0),
+            segment_limit_po2: Some(30),
+            base_path: Some("bonsai".to_string()),
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("base_path"));
+        assert!(err.contains("server_url"));
+        assert!(err.contains("workers"));
+        assert!(err.contains("segment_limit_po2"));
+
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_tls() {
+        let cert = write_config(".pem", "");
+        let config = Config {
+            tls_cert: Some(cert.path().to_path_buf()),
+            tls_client_ca: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("tls_cert requires tls_key"));
+        assert!(err.contains("tls_client_ca /nonexistent/ca.pem"));
+
+        let config = Config {
+            tls_cert: Some(cert.path().to_path_buf()),
+            tls_key: Some(cert.path().to_path_buf()),
+            ..Default::default()
+        };
+        let tls = config.server_options().unwrap().tls.unwrap();
+        assert_eq!(tls.key_path, cert.path());
+        assert!(tls.client_ca_path.is_none());
+
+        let config = Config {
+            listen_address: Some("unix:/run/bonsai.sock".to_string()),
+            ..config
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Unix domain sockets"));
+    }
+}
@@ -0,0 +1,139 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{Html, IntoResponse, Redirect},
+    Extension, Json,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{
+    error::Error,
+    prover::ProverHandle,
+    state::{AppState, BonsaiState, Resource, SessionStatus, Usage},
+};
+
+const INDEX_HTML: &str = include_str!("dashboard/index.html");
+const DASHBOARD_JS: &str = include_str!("dashboard/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("dashboard/dashboard.css");
+
+/// Redirects to the dashboard with a trailing slash so relative asset and API
+/// URLs resolve correctly, including behind a path prefix.
+pub(crate) async fn redirect() -> Redirect {
+    Redirect::permanent("dashboard/")
+}
+
+pub(crate) async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+pub(crate) async fn script() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        DASHBOARD_JS,
+    )
+}
+
+pub(crate) async fn stylesheet() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        DASHBOARD_CSS,
+    )
+}
+
+#[derive(Serialize)]
+pub(crate) struct QueueSummary {
+    /// Sessions waiting for the prover
+    queued: usize,
+    /// Sessions the prover is working on
+    running: usize,
+    /// Maximum number of sessions the queue holds before rejecting new ones
+    capacity: usize,
+}
+
+#[derive(Serialize)]
+pub(crate) struct StorageSummary {
+    images: Usage,
+    inputs: Usage,
+    receipts: Usage,
+}
+
+/// Overview of the server state shown by the dashboard.
+#[derive(Serialize)]
+pub(crate) struct Summary {
+    queue: QueueSummary,
+    /// Number of sessions per status
+    sessions: BTreeMap<String, usize>,
+    storage: StorageSummary,
+    ttl_secs: u64,
+}
+
+impl Summary {
+    pub(crate) fn new(state: &BonsaiState, queue_capacity: usize) -> Self {
+        let mut queued = 0;
+        let mut running = 0;
+        let mut sessions = BTreeMap::new();
+        for (_, info) in state.list_sessions() {
+            if info.status == SessionStatus::Running {
+                match info.started_at {
+                    Some(_) => running += 1,
+                    None => queued += 1,
+                }
+            }
+            *sessions.entry(info.status.to_string()).or_insert(0) += 1;
+        }
+
+        Self {
+            queue: QueueSummary {
+                queued,
+                running,
+                capacity: queue_capacity,
+            },
+            sessions,
+            storage: StorageSummary {
+                images: state.usage(Resource::Image),
+                inputs: state.usage(Resource::Input),
+                receipts: state.usage(Resource::Receipt),
+            },
+            ttl_secs: state.ttl.as_secs(),
+        }
+    }
+}
+
+pub(crate) async fn summary(
+    State(s): State<AppState>,
+    Extension(prover_handle): Extension<ProverHandle>,
+) -> Result<Json<Summary>, Error> {
+    let state = s.read()?;
+    Ok(Json(Summary::new(
+        &state,
+        prover_handle.sender.max_capacity(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::SessionInfo;
+    use std::time::Duration;
+
+    #[test]
+    fn test_summary_counts_sessions() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        for id in ["queued", "running", "done"] {
+            state.put_session_info(id.to_string(), SessionInfo::new("image".to_string(), None));
+        }
+        state.mark_session_started("running");
+        state.mark_session_started("done");
+        state.put_session("done".to_string(), SessionStatus::Succeeded, None);
+        state.put_image("image".to_string(), vec![1, 2, 3].into(), None);
+
+        let summary = Summary::new(&state, 8);
+        assert_eq!(summary.queue.queued, 1);
+        assert_eq!(summary.queue.running, 1);
+        assert_eq!(summary.queue.capacity, 8);
+        assert_eq!(summary.sessions["RUNNING"], 2);
+        assert_eq!(summary.sessions["SUCCEEDED"], 1);
+        assert_eq!(summary.storage.images.bytes, 3);
+    }
+}
@@ -0,0 +1,145 @@
+"use strict";
+
+// The dashboard is served from `<base>/dashboard/`, so API routes live one level up.
+const api = (path) => `../${path}`;
+
+const REFRESH_INTERVAL_MS = 5000;
+
+function formatBytes(bytes) {
+  const units = ["B", "KiB", "MiB", "GiB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+function formatTime(unixSecs) {
+  return new Date(unixSecs * 1000).toLocaleString();
+}
+
+function formatDuration(ms) {
+  if (ms === null || ms === undefined) {
+    return "";
+  }
+  return ms < 1000 ? `${ms} ms` : `${(ms / 1000).toFixed(1)} s`;
+}
+
+function shortId(id) {
+  return id.length > 16 ? `${id.slice(0, 8)}…${id.slice(-6)}` : id;
+}
+
+function cell(row, text, className) {
+  const td = row.insertCell();
+  td.textContent = text;
+  if (className) {
+    td.className = className;
+  }
+  td.title = text;
+  return td;
+}
+
+function link(td, text, href) {
+  const a = document.createElement("a");
+  a.textContent = text;
+  a.href = href;
+  td.appendChild(a);
+}
+
+async function getJson(path) {
+  const res = await fetch(api(path));
+  if (!res.ok) {
+    throw new Error(`${path}: ${res.status}`);
+  }
+  return res.json();
+}
+
+async function stopSession(id) {
+  if (!confirm(`Cancel session ${id}?`)) {
+    return;
+  }
+  await fetch(api(`sessions/stop/${id}`));
+  refresh();
+}
+
+function renderSummary(summary) {
+  document.getElementById("queued").textContent = summary.queue.queued;
+  document.getElementById("running").textContent = summary.queue.running;
+  document.getElementById("capacity").textContent = summary.queue.capacity;
+  document.getElementById("ttl").textContent = `${summary.ttl_secs} s`;
+
+  const body = document.getElementById("storage");
+  body.replaceChildren();
+  for (const [kind, usage] of Object.entries(summary.storage)) {
+    const row = body.insertRow();
+    cell(row, kind);
+    cell(row, usage.count);
+    cell(row, formatBytes(usage.bytes));
+    cell(row, formatBytes(usage.on_disk_bytes));
+  }
+}
+
+function renderSessions(list) {
+  const body = document.getElementById("sessions");
+  body.replaceChildren();
+  for (const session of list.sessions) {
+    const row = body.insertRow();
+    cell(row, shortId(session.uuid), "id").title = session.uuid;
+    cell(row, session.status, `status-${session.status}`);
+    cell(row, shortId(session.image_id), "id").title = session.image_id;
+    cell(row, formatTime(session.created_at));
+    cell(row, formatDuration(session.duration_ms));
+    cell(row, session.stats ? session.stats.total_cycles : "");
+    cell(row, session.stats ? session.stats.segments : "");
+
+    const actions = row.insertCell();
+    link(actions, "Logs", api(`sessions/logs/${session.uuid}`));
+    if (session.status === "SUCCEEDED") {
+      link(actions, "Receipt", api(`receipts/${session.uuid}`));
+    }
+    if (session.status === "RUNNING") {
+      const button = document.createElement("button");
+      button.textContent = "Cancel";
+      button.onclick = () => stopSession(session.uuid);
+      actions.appendChild(button);
+    }
+  }
+}
+
+function renderImages(images) {
+  const body = document.getElementById("images");
+  body.replaceChildren();
+  for (const image of images) {
+    const row = body.insertRow();
+    cell(row, shortId(image.image_id), "id").title = image.image_id;
+    cell(row, formatBytes(image.size));
+    cell(row, formatTime(image.uploaded_at));
+    cell(row, formatTime(image.expires_at));
+    link(row.insertCell(), "Download", api(`images/${image.image_id}`));
+  }
+}
+
+async function refresh() {
+  const status = document.getElementById("status-filter").value;
+  const query = status ? `?status=${status}` : "";
+  try {
+    const [summary, sessions, images] = await Promise.all([
+      getJson("dashboard/summary"),
+      getJson(`sessions${query}`),
+      getJson("images"),
+    ]);
+    renderSummary(summary);
+    renderSessions(sessions);
+    renderImages(images);
+    document.getElementById("updated").textContent =
+      `Updated ${new Date().toLocaleTimeString()}`;
+  } catch (err) {
+    document.getElementById("updated").textContent = `Refresh failed: ${err.message}`;
+  }
+}
+
+document.getElementById("status-filter").onchange = refresh;
+refresh();
+setInterval(refresh, REFRESH_INTERVAL_MS);
@@ -19,13 +19,21 @@ use std::{
     sync::PoisonError,
 };
 
-use axum::{http::StatusCode, response};
+use axum::{
+    extract::{rejection::JsonRejection, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{self, IntoResponse},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
 use tokio::task::JoinError;
 
+use crate::state::Resource;
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum Error {
-    #[error("ImageIdExists")]
-    ImageIdExists,
     #[error("Bincode error")]
     Bincode(#[from] bincode::Error),
     #[error("Hex decode error")]
@@ -44,6 +52,43 @@ pub(crate) enum Error {
     ProverQueueFull,
     #[error("Unable to resolve server URL from headers")]
     ServerUrlResolution,
+    #[error("Request body exceeds the configured limit")]
+    PayloadTooLarge,
+    #[error("Request body error")]
+    Body(#[from] axum::Error),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Session timed out after {0:?}")]
+    SessionTimedOut(std::time::Duration),
+    #[error("{0}")]
+    SessionFailed(String),
+    #[error("Invalid JSON body")]
+    InvalidJson(#[from] JsonRejection),
+    #[error("Image not found: {0}")]
+    ImageNotFound(String),
+    #[error("Input not found: {0}")]
+    InputNotFound(String),
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+    #[error("Receipt not found: {0}")]
+    ReceiptNotFound(String),
+    #[error("{0} expired and was removed: {1}")]
+    Gone(Resource, String),
+    #[error("{0} is still needed by a queued session: {1}")]
+    InUse(Resource, String),
+    #[error("Session is still running: {0}")]
+    SessionRunning(String),
+    #[error("Missing or invalid admin token")]
+    Unauthorized,
+    #[error("Server is shutting down and no longer accepts sessions")]
+    ShuttingDown,
+    /// With the time until the quota frees up, if known
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String, Option<std::time::Duration>),
+    #[error("Rate limit exceeded, retry in {0:?}")]
+    RateLimited(std::time::Duration),
+    #[error("Client risc0-zkvm version {0} is not supported, the server supports {}", .1.join(", "))]
+    UnsupportedVersion(String, Vec<String>),
 }
 
 impl<T> From<PoisonError<T>> for Error {
@@ -55,16 +100,94 @@ impl<T> From<PoisonError<T>> for Error {
 impl Error {
     pub fn status_code(&self) -> StatusCode {
         match self {
-            Error::ImageIdExists => StatusCode::NO_CONTENT,
-            Error::ProverQueueFull => StatusCode::SERVICE_UNAVAILABLE,
-            Error::ServerUrlResolution => StatusCode::BAD_REQUEST,
+            Error::ProverQueueFull | Error::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Error::ServerUrlResolution
+            | Error::Body { .. }
+            | Error::InvalidRequest(_)
+            | Error::UnsupportedVersion(..) => StatusCode::BAD_REQUEST,
+            Error::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::InvalidJson(rejection) => rejection.status(),
+            Error::ImageNotFound(_)
+            | Error::InputNotFound(_)
+            | Error::SessionNotFound(_)
+            | Error::ReceiptNotFound(_) => StatusCode::NOT_FOUND,
+            Error::Gone(..) => StatusCode::GONE,
+            Error::InUse(..) | Error::SessionRunning(_) => StatusCode::CONFLICT,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::QuotaExceeded(..) | Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Poisoned
+            | Error::Bincode { .. }
+            | Error::Unspecified { .. }
+            | Error::Hex { .. }
+            | Error::IO { .. }
+            | Error::SerdeJson { .. }
+            | Error::Join { .. }
+            | Error::SessionTimedOut(_)
+            | Error::SessionFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable, machine-readable identifier of the error returned in the `code` field.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::ProverQueueFull => "PROVER_QUEUE_FULL",
+            Error::ServerUrlResolution => "SERVER_URL_UNRESOLVABLE",
+            Error::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            Error::Body { .. } => "INVALID_BODY",
+            Error::InvalidRequest(_) => "INVALID_REQUEST",
+            Error::InvalidJson(_) => "INVALID_JSON",
+            Error::ImageNotFound(_) => "IMAGE_NOT_FOUND",
+            Error::InputNotFound(_) => "INPUT_NOT_FOUND",
+            Error::SessionNotFound(_) => "SESSION_NOT_FOUND",
+            Error::ReceiptNotFound(_) => "RECEIPT_NOT_FOUND",
+            Error::Gone(Resource::Image, _) => "IMAGE_GONE",
+            Error::Gone(Resource::Input, _) => "INPUT_GONE",
+            Error::Gone(Resource::Session, _) => "SESSION_GONE",
+            Error::Gone(Resource::Receipt, _) => "RECEIPT_GONE",
+            Error::InUse(..) => "RESOURCE_IN_USE",
+            Error::SessionRunning(_) => "SESSION_RUNNING",
+            Error::SessionTimedOut(_) => "SESSION_TIMED_OUT",
+            Error::SessionFailed(_) => "SESSION_FAILED",
+            Error::Unauthorized => "UNAUTHORIZED",
+            Error::ShuttingDown => "SHUTTING_DOWN",
+            Error::QuotaExceeded(..) => "QUOTA_EXCEEDED",
+            Error::RateLimited(_) => "RATE_LIMITED",
+            Error::UnsupportedVersion(..) => "UNSUPPORTED_VERSION",
             Error::Poisoned
             | Error::Bincode { .. }
             | Error::Unspecified { .. }
             | Error::Hex { .. }
             | Error::IO { .. }
             | Error::SerdeJson { .. }
-            | Error::Join { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            | Error::Join { .. } => "INTERNAL_ERROR",
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            Error::InUse(resource, id) => {
+                Some(json!({ "resource": resource.to_string(), "id": id }))
+            }
+            Error::ImageNotFound(id) | Error::Gone(Resource::Image, id) => {
+                Some(json!({ "image_id": id }))
+            }
+            Error::InputNotFound(id) | Error::Gone(Resource::Input, id) => {
+                Some(json!({ "input_id": id }))
+            }
+            Error::SessionNotFound(id)
+            | Error::Gone(Resource::Session, id)
+            | Error::SessionRunning(id) => Some(json!({ "session_id": id })),
+            Error::ReceiptNotFound(id) | Error::Gone(Resource::Receipt, id) => {
+                Some(json!({ "receipt_id": id }))
+            }
+            Error::UnsupportedVersion(client_version, supported) => Some(json!({
+                "client_version": client_version,
+                "supported_versions": supported,
+            })),
+            Error::RateLimited(retry_after) | Error::QuotaExceeded(_, Some(retry_after)) => {
+                Some(json!({ "retry_after_ms": retry_after.as_millis() as u64 }))
+            }
+            _ => None,
         }
     }
 }
@@ -90,18 +213,62 @@ impl response::IntoResponse for Error {
         // prepare an error message
         let message = match status {
             // for 500 Internal Server Error, we only return the error name, e.g.:
-            // "IO Error"
+            // "IO Error"; its causes may reveal internals and only go to the log
             StatusCode::INTERNAL_SERVER_ERROR => self.to_string(),
             // otherwise, use the alternative anyhow formatting: colon-separated chain, e.g.:
             // "IO error: broken pipe"
             _ => format!("{}", DisplayErrorCauses(&self)),
         };
 
-        // return the message as simple text
-        (status, message).into_response()
+        let body = ErrorBody {
+            code: self.code(),
+            message,
+            request_id: None,
+            details: self.details(),
+        };
+        let mut response = (status, Json(&body)).into_response();
+        if let Error::RateLimited(retry_after) | Error::QuotaExceeded(_, Some(retry_after)) = &self
+        {
+            // whole seconds, rounded up so clients do not retry too early
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.max(1).into());
+        }
+        // picked up by `attach_request_id` to fill in the request id
+        response.extensions_mut().insert(body);
+        response
     }
 }
 
+/// JSON body of every error response.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Middleware adding the `x-request-id` of the request to JSON error bodies.
+pub(crate) async fn attach_request_id(request: Request, next: Next) -> response::Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let mut response = next.run(request).await;
+    if let Some(mut body) = response.extensions_mut().remove::<ErrorBody>() {
+        body.request_id = request_id;
+        let (mut parts, _) = response.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return (parts, Json(body)).into_response();
+    }
+    response
+}
+
 /// Provides a `Display` impl for `Error` that outputs the full chain of errors.
 #[derive(Debug)]
 pub(crate) struct DisplayErrorCauses<E: StdError>(pub E);
@@ -147,6 +314,62 @@ mod tests {
         io_err.into()
     }
 
+    async fn body_json(response: response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_not_found_response() {
+        let response = Error::SessionNotFound("abc".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            body_json(response).await,
+            json!({
+                "code": "SESSION_NOT_FOUND",
+                "message": "Session not found: abc",
+                "details": { "session_id": "abc" },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_error_response() {
+        let response = io_err("mocked").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body_json(response).await,
+            json!({
+                "code": "INTERNAL_ERROR",
+                "message": "IO error",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response() {
+        let response = Error::RateLimited(std::time::Duration::from_millis(1500)).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        assert_eq!(body_json(response).await["code"], "RATE_LIMITED");
+    }
+
+    #[tokio::test]
+    async fn test_gone_response() {
+        let response = Error::Gone(Resource::Receipt, "abc".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::GONE);
+        assert_eq!(
+            body_json(response).await,
+            json!({
+                "code": "RECEIPT_GONE",
+                "message": "Receipt expired and was removed: abc",
+                "details": { "receipt_id": "abc" },
+            })
+        );
+    }
+
     #[test]
     fn test_display() {
         let err = io_err(io_err("mocked"));
@@ -0,0 +1,648 @@
+use axum::http::HeaderMap;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::{listener::Peer, trusted_proxies::TrustedProxies};
+
+/// The parameters one proxy added to the `Forwarded` header (RFC 7239), i.e. one
+/// comma-separated `forwarded-element`. Unknown parameters are accepted and ignored.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ForwardedElement {
+    /// `by`: the interface the request came in on at the proxy
+    pub(crate) by: Option<Node>,
+    /// `for`: the client that sent the request to the proxy
+    pub(crate) for_: Option<Node>,
+    /// `host`: the Host header the proxy received
+    pub(crate) host: Option<String>,
+    /// `proto`: the scheme the proxy was contacted with, lowercased
+    pub(crate) proto: Option<String>,
+}
+
+/// A node identifier as used by the `for` and `by` parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Node {
+    pub(crate) name: NodeName,
+    pub(crate) port: Option<NodePort>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum NodeName {
+    Ip(IpAddr),
+    /// The proxy does not know (or does not want to tell) the address
+    Unknown,
+    /// An identifier like `_hidden` that stands in for the real address
+    Obfuscated(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum NodePort {
+    Port(u16),
+    Obfuscated(String),
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub(crate) enum ForwardedError {
+    #[error("unexpected {0:?} at offset {1}")]
+    Unexpected(char, usize),
+    #[error("unexpected end of header")]
+    UnexpectedEnd,
+    #[error("unterminated quoted string")]
+    UnterminatedQuote,
+    #[error("parameter {0:?} occurs more than once in an element")]
+    DuplicateParameter(String),
+    #[error("invalid value {1:?} for parameter {0:?}")]
+    InvalidValue(&'static str, String),
+}
+
+/// Parses every `Forwarded` header of a request as one list, as if the values were
+/// joined with commas.
+///
+/// Returns `None` if there is no such header or any value is malformed; a header that
+/// is only partially understood is not trusted at all.
+pub(crate) fn parse_forwarded(headers: &HeaderMap) -> Option<Vec<ForwardedElement>> {
+    let mut elements = vec![];
+    for value in headers.get_all("forwarded") {
+        let value = value.to_str().ok()?;
+        match parse(value) {
+            Ok(parsed) => elements.extend(parsed),
+            Err(err) => {
+                tracing::debug!("Ignoring malformed Forwarded header {value:?}: {err}");
+                return None;
+            }
+        }
+    }
+    (!elements.is_empty()).then_some(elements)
+}
+
+/// Parses a single `Forwarded` header value.
+///
+/// Beyond RFC 7239, unquoted values may contain `:`, `[` and `]`, which proxies commonly
+/// emit for ports and IPv6 addresses (e.g. `host=example.com:8443`) even though the
+/// grammar requires them to be quoted. Whitespace around `;` is tolerated as well.
+pub(crate) fn parse(value: &str) -> Result<Vec<ForwardedElement>, ForwardedError> {
+    let mut parser = Parser {
+        input: value,
+        pos: 0,
+    };
+    let mut elements = vec![];
+    loop {
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => break,
+            // empty list elements are allowed by the `#rule`
+            Some(',') => {
+                parser.pos += 1;
+                continue;
+            }
+            Some(_) => elements.push(parser.element()?),
+        }
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => break,
+            Some(',') => parser.pos += 1,
+            Some(c) => return Err(ForwardedError::Unexpected(c, parser.pos)),
+        }
+    }
+    Ok(elements)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// `forwarded-element = [ forwarded-pair ] *( ";" [ forwarded-pair ] )`
+    fn element(&mut self) -> Result<ForwardedElement, ForwardedError> {
+        let mut element = ForwardedElement::default();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some(',') => return Ok(element),
+                Some(';') => {
+                    self.pos += 1;
+                    continue;
+                }
+                Some(_) => {}
+            }
+            let name = self.token(false)?;
+            match self.peek() {
+                Some('=') => self.pos += 1,
+                Some(c) => return Err(ForwardedError::Unexpected(c, self.pos)),
+                None => return Err(ForwardedError::UnexpectedEnd),
+            }
+            let value = match self.peek() {
+                Some('"') => self.quoted_string()?,
+                _ => self.token(true)?.to_string(),
+            };
+            element.set(name, value)?;
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some(',') => return Ok(element),
+                Some(';') => self.pos += 1,
+                Some(c) => return Err(ForwardedError::Unexpected(c, self.pos)),
+            }
+        }
+    }
+
+    /// `token = 1*tchar`, optionally extended with the characters of unquoted
+    /// addresses and ports.
+    fn token(&mut self, lenient: bool) -> Result<&'a str, ForwardedError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(is_tchar(c) || lenient && matches!(c, ':' | '[' | ']')) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        if self.pos == start {
+            return Err(match self.peek() {
+                Some(c) => ForwardedError::Unexpected(c, self.pos),
+                None => ForwardedError::UnexpectedEnd,
+            });
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    /// `quoted-string = DQUOTE *( qdtext / quoted-pair ) DQUOTE`
+    fn quoted_string(&mut self) -> Result<String, ForwardedError> {
+        // opening quote
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.input[self.pos..].chars();
+        while let Some(c) = chars.next() {
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = chars.next().ok_or(ForwardedError::UnterminatedQuote)?;
+                    self.pos += escaped.len_utf8();
+                    value.push(escaped);
+                }
+                c if c.is_control() && c != '\t' => {
+                    return Err(ForwardedError::Unexpected(c, self.pos - c.len_utf8()))
+                }
+                c => value.push(c),
+            }
+        }
+        Err(ForwardedError::UnterminatedQuote)
+    }
+}
+
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+impl ForwardedElement {
+    fn set(&mut self, name: &str, value: String) -> Result<(), ForwardedError> {
+        fn set_once<T>(slot: &mut Option<T>, name: &str, value: T) -> Result<(), ForwardedError> {
+            if slot.replace(value).is_some() {
+                return Err(ForwardedError::DuplicateParameter(
+                    name.to_ascii_lowercase(),
+                ));
+            }
+            Ok(())
+        }
+
+        // parameter names are case-insensitive
+        match name.to_ascii_lowercase().as_str() {
+            "by" => {
+                let node = value
+                    .parse()
+                    .map_err(|_| ForwardedError::InvalidValue("by", value))?;
+                set_once(&mut self.by, name, node)
+            }
+            "for" => {
+                let node = value
+                    .parse()
+                    .map_err(|_| ForwardedError::InvalidValue("for", value))?;
+                set_once(&mut self.for_, name, node)
+            }
+            "host" => {
+                if !is_valid_host(&value) {
+                    return Err(ForwardedError::InvalidValue("host", value));
+                }
+                set_once(&mut self.host, name, value)
+            }
+            "proto" => {
+                if !is_valid_scheme(&value) {
+                    return Err(ForwardedError::InvalidValue("proto", value));
+                }
+                set_once(&mut self.proto, name, value.to_ascii_lowercase())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `host = uri-host [ ":" port ]`, without user info.
+fn is_valid_host(value: &str) -> bool {
+    !value.is_empty()
+        && !value.contains(['@', '/', '?', '#'])
+        && axum::http::uri::Authority::from_str(value).is_ok()
+}
+
+/// `scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`
+fn is_valid_scheme(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// `obfnode = "_" 1*( ALPHA / DIGIT / "." / "_" / "-")`, also used for `obfport`
+fn is_obfuscated(value: &str) -> bool {
+    value.strip_prefix('_').is_some_and(|rest| {
+        !rest.is_empty()
+            && rest
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    })
+}
+
+impl FromStr for NodePort {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if is_obfuscated(s) {
+            Ok(Self::Obfuscated(s.to_string()))
+        } else if (1..=5).contains(&s.len()) && s.chars().all(|c| c.is_ascii_digit()) {
+            s.parse().map(Self::Port).map_err(|_| ())
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// `node = nodename [ ":" node-port ]`, where IPv6 addresses are enclosed in brackets.
+impl FromStr for Node {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, port) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (addr, rest) = rest.split_once(']').ok_or(())?;
+                let addr: Ipv6Addr = addr.parse().map_err(|_| ())?;
+                let port = match rest {
+                    "" => None,
+                    rest => Some(rest.strip_prefix(':').ok_or(())?),
+                };
+                (NodeName::Ip(addr.into()), port)
+            }
+            None => {
+                let (name, port) = match s.split_once(':') {
+                    Some((name, port)) => (name, Some(port)),
+                    None => (s, None),
+                };
+                let name = if name.eq_ignore_ascii_case("unknown") {
+                    NodeName::Unknown
+                } else if is_obfuscated(name) {
+                    NodeName::Obfuscated(name.to_string())
+                } else {
+                    // IPv6 addresses must be bracketed, so only IPv4 is valid here
+                    NodeName::Ip(IpAddr::V4(name.parse().map_err(|_| ())?))
+                };
+                (name, port)
+            }
+        };
+        let port = port.map(str::parse).transpose()?;
+        Ok(Self { name, port })
+    }
+}
+
+impl From<IpAddr> for Node {
+    fn from(addr: IpAddr) -> Self {
+        Self {
+            name: NodeName::Ip(addr),
+            port: None,
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, &self.port) {
+            (NodeName::Ip(IpAddr::V6(addr)), Some(_)) => write!(f, "[{addr}]")?,
+            (NodeName::Ip(addr), _) => write!(f, "{addr}")?,
+            (NodeName::Unknown, _) => f.write_str("unknown")?,
+            (NodeName::Obfuscated(name), _) => f.write_str(name)?,
+        }
+        match &self.port {
+            Some(NodePort::Port(port)) => write!(f, ":{port}"),
+            Some(NodePort::Obfuscated(port)) => write!(f, ":{port}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Identifies the client that originated a request, for logging.
+///
+/// Forwarded headers are only consulted when `peer` is a trusted proxy. The chain of
+/// `for` nodes in `Forwarded` (or, without it, `X-Forwarded-For`) is then walked from
+/// the nearest hop backwards, skipping trusted proxies; the first other node is the
+/// client. Nothing further left can be trusted, as that node may have made it up.
+pub(crate) fn client(headers: &HeaderMap, peer: Peer, trusted_proxies: &TrustedProxies) -> String {
+    let peer_node = match peer {
+        Peer::Ip(addr) => Node::from(addr),
+        Peer::Unix => return "unix".to_string(),
+    };
+    if !trusted_proxies.contains(peer) {
+        return peer_node.to_string();
+    }
+    let Some(hops) = forwarded_for(headers).or_else(|| x_forwarded_for(headers)) else {
+        return peer_node.to_string();
+    };
+    let skipped = trusted_hops(&hops, trusted_proxies);
+    hops[hops.len() - 1 - skipped].to_string()
+}
+
+/// Counts the trusted proxies at the end of a chain of hops, leaving out the first hop.
+///
+/// The element that many places from the end of a list of forwarded values is the one
+/// a trusted proxy recorded about an untrusted node (or, if all are trusted, the first
+/// one); values further left may have been made up by that node.
+pub(crate) fn trusted_hops(hops: &[Node], trusted_proxies: &TrustedProxies) -> usize {
+    hops.iter()
+        .rev()
+        .take_while(|hop| match hop.name {
+            NodeName::Ip(addr) => trusted_proxies.contains(Peer::Ip(addr)),
+            _ => false,
+        })
+        .count()
+        .min(hops.len().saturating_sub(1))
+}
+
+/// `for` nodes of all `Forwarded` elements, with `unknown` for elements without one.
+fn forwarded_for(headers: &HeaderMap) -> Option<Vec<Node>> {
+    Some(for_nodes(&parse_forwarded(headers)?))
+}
+
+/// `for` nodes of `elements`, with `unknown` for elements without one.
+pub(crate) fn for_nodes(elements: &[ForwardedElement]) -> Vec<Node> {
+    elements
+        .iter()
+        .map(|element| {
+            element.for_.clone().unwrap_or(Node {
+                name: NodeName::Unknown,
+                port: None,
+            })
+        })
+        .collect()
+}
+
+/// Addresses listed in `X-Forwarded-For`, which (unlike `for`) may be bare IPv6 addresses.
+pub(crate) fn x_forwarded_for(headers: &HeaderMap) -> Option<Vec<Node>> {
+    let mut hops = vec![];
+    for value in headers.get_all("x-forwarded-for") {
+        for entry in value.to_str().ok()?.split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let node = entry
+                .parse::<IpAddr>()
+                .map(Node::from)
+                .or_else(|_| entry.parse())
+                .ok()?;
+            hops.push(node);
+        }
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn node(s: &str) -> Node {
+        s.parse().unwrap()
+    }
+
+    fn element(s: &str) -> ForwardedElement {
+        let mut elements = parse(s).unwrap();
+        assert_eq!(elements.len(), 1, "{s}");
+        elements.remove(0)
+    }
+
+    #[test]
+    fn test_parse_rfc_examples() {
+        // examples from RFC 7239 sections 4 and 7
+        assert_eq!(element(r#"for="_gazonk""#).for_, Some(node("_gazonk")));
+        assert_eq!(
+            element(r#"For="[2001:db8:cafe::17]:4711""#).for_,
+            Some(node("[2001:db8:cafe::17]:4711"))
+        );
+
+        let e = element("for=192.0.2.60;proto=http;by=203.0.113.43");
+        assert_eq!(e.for_, Some(node("192.0.2.60")));
+        assert_eq!(e.proto.as_deref(), Some("http"));
+        assert_eq!(e.by, Some(node("203.0.113.43")));
+
+        let elements = parse("for=192.0.2.43, for=198.51.100.17").unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[1].for_, Some(node("198.51.100.17")));
+
+        let elements = parse(r#"for=192.0.2.43, for="[2001:db8:cafe::17]", for=unknown"#).unwrap();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(
+            elements[1].for_.as_ref().unwrap().name,
+            NodeName::Ip("2001:db8:cafe::17".parse().unwrap())
+        );
+        assert_eq!(elements[2].for_.as_ref().unwrap().name, NodeName::Unknown);
+
+        assert_eq!(
+            element(r#"for="_Hidden:_Port""#).for_,
+            Some(Node {
+                name: NodeName::Obfuscated("_Hidden".to_string()),
+                port: Some(NodePort::Obfuscated("_Port".to_string())),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_values() {
+        // separators inside quotes do not split elements or pairs
+        let e = element(r#"host="[::1]:8080";proto="HTTPS";for="192.0.2.1:443""#);
+        assert_eq!(e.host.as_deref(), Some("[::1]:8080"));
+        assert_eq!(e.proto.as_deref(), Some("https"));
+        assert_eq!(
+            e.for_,
+            Some(Node {
+                name: NodeName::Ip("192.0.2.1".parse().unwrap()),
+                port: Some(NodePort::Port(443)),
+            })
+        );
+
+        // quoted-pair escapes; unknown parameters may contain anything
+        let elements = parse(r#"ext="a,b;c=\"d\"";host=example.com, proto=http"#).unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].host.as_deref(), Some("example.com"));
+        assert_eq!(elements[1].proto.as_deref(), Some("http"));
+
+        assert_eq!(
+            element(r#"host="ex\ample.com""#).host.as_deref(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn test_parse_case_insensitive_names() {
+        let e = element("HOST=example.com;Proto=http;FOR=192.0.2.1;bY=_proxy");
+        assert_eq!(e.host.as_deref(), Some("example.com"));
+        assert_eq!(e.proto.as_deref(), Some("http"));
+        assert_eq!(e.for_, Some(node("192.0.2.1")));
+        assert_eq!(e.by, Some(node("_proxy")));
+        assert_eq!(element("for=UNKNOWN").for_.unwrap().name, NodeName::Unknown);
+    }
+
+    #[test]
+    fn test_parse_lenient_syntax() {
+        // unquoted ports and brackets, as emitted by many proxies
+        assert_eq!(
+            element("host=example.com:8443").host.as_deref(),
+            Some("example.com:8443")
+        );
+        assert_eq!(element("for=[::1]:80").for_, Some(node("[::1]:80")));
+        // whitespace around separators and empty elements or pairs
+        let elements = parse(" for=192.0.2.1 ; proto=https ,, ;for=192.0.2.2; ").unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].proto.as_deref(), Some("https"));
+        assert_eq!(elements[1].for_, Some(node("192.0.2.2")));
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        use ForwardedError::*;
+
+        assert_eq!(parse("for"), Err(UnexpectedEnd));
+        assert_eq!(parse("for="), Err(UnexpectedEnd));
+        assert_eq!(parse("for = 192.0.2.1"), Err(Unexpected(' ', 3)));
+        assert_eq!(parse("=192.0.2.1"), Err(Unexpected('=', 0)));
+        assert_eq!(parse(r#"for="192.0.2.1"#), Err(UnterminatedQuote));
+        assert_eq!(parse(r#"for="192.0.2.1\"#), Err(UnterminatedQuote));
+        assert_eq!(parse(r#"host="a"b"#), Err(Unexpected('b', 8)));
+        assert_eq!(parse("host=a b"), Err(Unexpected('b', 7)));
+        assert_eq!(
+            parse("for=192.0.2.1;For=192.0.2.2"),
+            Err(DuplicateParameter("for".to_string()))
+        );
+        assert!(matches!(parse("for=::1"), Err(InvalidValue("for", _))));
+        assert!(matches!(
+            parse("for=example.com"),
+            Err(InvalidValue("for", _))
+        ));
+        assert!(matches!(
+            parse("for=192.0.2.1:99999"),
+            Err(InvalidValue("for", _))
+        ));
+        assert!(matches!(parse("for=_"), Err(InvalidValue("for", _))));
+        assert!(matches!(
+            parse(r#"host="user@evil.com""#),
+            Err(InvalidValue("host", _))
+        ));
+        assert!(matches!(
+            parse(r#"host="a.com/path""#),
+            Err(InvalidValue("host", _))
+        ));
+        assert!(matches!(
+            parse("proto=1http"),
+            Err(InvalidValue("proto", _))
+        ));
+        assert!(matches!(
+            parse(r#"proto="ht tp""#),
+            Err(InvalidValue("proto", _))
+        ));
+    }
+
+    #[test]
+    fn test_node_display() {
+        for s in [
+            "192.0.2.1",
+            "192.0.2.1:80",
+            "[2001:db8::1]:4711",
+            "_hidden:_port",
+            "unknown",
+        ] {
+            assert_eq!(node(s).to_string(), s);
+        }
+        assert_eq!(node("[2001:db8::1]").to_string(), "2001:db8::1");
+    }
+
+    #[test]
+    fn test_parse_forwarded_combines_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(parse_forwarded(&headers).is_none());
+
+        headers.append("forwarded", HeaderValue::from_static("for=192.0.2.1"));
+        headers.append("forwarded", HeaderValue::from_static("for=192.0.2.2"));
+        assert_eq!(parse_forwarded(&headers).unwrap().len(), 2);
+
+        // one malformed value invalidates the whole header
+        headers.append("forwarded", HeaderValue::from_static("for=\"oops"));
+        assert!(parse_forwarded(&headers).is_none());
+    }
+
+    #[test]
+    fn test_client() {
+        let proxies: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let proxy = Peer::Ip("10.0.0.1".parse().unwrap());
+        let direct = Peer::Ip("203.0.113.9".parse().unwrap());
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(client(&headers, proxy, &proxies), "10.0.0.1");
+        assert_eq!(client(&headers, Peer::Unix, &proxies), "unix");
+
+        // the rightmost untrusted hop is the client; anything before it may be spoofed
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("for=198.51.100.1, for=192.0.2.7, for=10.0.0.2"),
+        );
+        assert_eq!(client(&headers, proxy, &proxies), "192.0.2.7");
+        // forwarded headers from untrusted peers are ignored
+        assert_eq!(client(&headers, direct, &proxies), "203.0.113.9");
+
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static(r#"for="[2001:db8::1]:4711";proto=https"#),
+        );
+        assert_eq!(client(&headers, proxy, &proxies), "[2001:db8::1]:4711");
+
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("for=_hidden, proto=http"),
+        );
+        assert_eq!(client(&headers, proxy, &proxies), "unknown");
+
+        // only trusted proxies in the chain: the leftmost one is all we know
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("for=10.1.1.1, for=10.2.2.2"),
+        );
+        assert_eq!(client(&headers, proxy, &proxies), "10.1.1.1");
+
+        // X-Forwarded-For is used when there is no valid Forwarded header
+        headers.insert("forwarded", HeaderValue::from_static("for=\"oops"));
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 2001:db8::2, 10.0.0.3"),
+        );
+        assert_eq!(client(&headers, proxy, &proxies), "2001:db8::2");
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("not-an-ip"));
+        assert_eq!(client(&headers, proxy, &proxies), "10.0.0.1");
+    }
+}
@@ -12,34 +12,75 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod admin;
+mod backend;
+mod blob;
+pub mod config;
+mod dashboard;
 mod error;
+mod forwarded;
+mod listener;
+mod mock;
 mod prover;
+mod quota;
+mod r0vm;
+mod rate_limit;
 mod routes;
+mod session_log;
 mod state;
+mod telemetry;
+mod tls;
+mod trusted_proxies;
 mod url_resolver;
 pub mod version;
 
 use crate::{
-    prover::{Prover, ProverHandle},
+    blob::BlobOptions,
+    error::attach_request_id,
+    listener::Peer,
+    prover::{ExecutorLimits, Prover, ProverControl, ProverHandle, ProverSettings},
+    quota::SharedQuotas,
+    rate_limit::{rate_limit, RateLimiter, SharedRateLimiter},
     routes::{
-        create_session, create_snark, get_image_upload, get_input_upload, get_receipt,
-        get_receipt_upload, health_check, put_image_upload, put_input_upload, put_receipt,
-        resolved_server_url, session_status, snark_status,
+        create_session, create_snark, delete_image, delete_input, delete_receipt, delete_session,
+        get_image, get_image_upload, get_input_upload, get_receipt, get_receipt_upload, head_image,
+        health_check, list_images, list_sessions, put_image_upload, put_input_upload, put_receipt,
+        resolved_server_url, session_logs, session_status, snark_status, stop_session, user_quotas,
+        version,
     },
     state::BonsaiState,
-    url_resolver::{ServerUrlResolver, SharedUrlResolver},
+    telemetry::make_request_span,
+    url_resolver::{fill_host_header, normalize_base_path, ServerUrlResolver, SharedUrlResolver},
+    version::{check_client_version, SupportedVersions},
+};
+
+pub use crate::{
+    backend::Backend,
+    listener::Listener,
+    mock::{MockRule, MockScript, SessionRange},
+    quota::{Quota, Quotas},
+    rate_limit::{RateLimit, RateLimits, RouteGroup},
+    tls::TlsOptions,
+    trusted_proxies::TrustedProxies,
 };
 use anyhow::Context;
 use axum::{
     extract::DefaultBodyLimit,
-    routing::{get, post, put},
+    middleware,
+    routing::{delete, get, post, put},
     Extension, Router,
 };
+use axum_server::tls_rustls::RustlsConfig;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
-use tokio::{net::TcpListener, sync::mpsc, time};
-use tower_http::trace::{DefaultOnRequest, TraceLayer};
-use tracing::{info, Level};
+use tokio::{sync::mpsc, time};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{DefaultOnRequest, TraceLayer},
+};
+use tracing::{info, warn, Level};
 use url::Url;
 
 pub struct ServerOptions {
@@ -47,51 +88,242 @@ pub struct ServerOptions {
     pub ttl: Duration,
     pub channel_buffer_size: usize,
     pub cleanup_interval: Duration,
+    /// Maximum size of an uploaded image, input or receipt in bytes
+    pub max_body_size: usize,
+    /// Uploads larger than this many bytes are spilled to disk instead of kept in memory
+    pub spill_threshold: usize,
+    /// Directory for spilled uploads (defaults to the system temp directory)
+    pub spill_dir: Option<PathBuf>,
+    /// Default segment size as a power of two
+    pub segment_limit_po2: u32,
+    /// Maximum number of executor cycles per session, unlimited if `None`
+    pub max_cycles: Option<u64>,
+    /// Maximum wall-clock time per session, unlimited if `None`
+    pub session_timeout: Option<Duration>,
+    /// Maximum number of bytes of guest stdout/stderr kept per session
+    pub session_log_capacity: usize,
+    /// Maximum number of sessions proven concurrently
+    pub workers: usize,
+    /// Bearer token for the `/admin` API, which is disabled if `None`
+    pub admin_token: Option<String>,
+    /// How long running sessions may take to finish once shutdown is requested
+    pub shutdown_timeout: Duration,
+    /// Serve HTTPS with these certificates instead of plain HTTP
+    pub tls: Option<TlsOptions>,
+    /// Peers allowed to set the server URL through `Forwarded`/`X-Forwarded-*` headers
+    pub trusted_proxies: TrustedProxies,
+    /// Path prefix the API is served under, e.g. `/bonsai`; served at the root if `None`
+    pub base_path: Option<String>,
+    /// Per API key limits on sessions, cycles and storage
+    pub quotas: Quotas,
+    /// Request rate limits per route group, applied per API key or client IP
+    pub rate_limits: RateLimits,
+    /// rzup installation to discover r0vm binaries of other zkVM versions in, e.g. `~/.risc0`
+    pub risc0_home: Option<PathBuf>,
+    /// Prover running the sessions of clients of the server's own zkVM version
+    pub backend: Backend,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn app(
     state: Arc<RwLock<BonsaiState>>,
     prover_handle: ProverHandle,
     url_resolver: SharedUrlResolver,
+    blob_options: BlobOptions,
+    executor_limits: ExecutorLimits,
+    quotas: SharedQuotas,
+    rate_limiter: SharedRateLimiter,
+    versions: SupportedVersions,
+    admin_token: Option<String>,
+    base_path: &str,
 ) -> Router {
-    Router::new()
+    let max_body_size = blob_options.max_body_size;
+    let span_resolver = Arc::clone(&url_resolver);
+    let mut router = Router::new()
         .route("/health", get(health_check))
+        .route("/metrics", get(rate_limit::metrics))
+        .route("/version", get(version))
         .route("/resolved-server-url", get(resolved_server_url))
         .route("/images/upload/:image_id", get(get_image_upload))
-        .route("/images/:image_id", put(put_image_upload))
+        .route("/images", get(list_images))
+        .route(
+            "/images/:image_id",
+            put(put_image_upload)
+                .get(get_image)
+                .head(head_image)
+                .delete(delete_image),
+        )
         .route("/inputs/upload", get(get_input_upload))
-        .route("/inputs/:input_id", put(put_input_upload))
+        .route(
+            "/inputs/:input_id",
+            put(put_input_upload).delete(delete_input),
+        )
+        .route("/sessions", get(list_sessions))
         .route("/sessions/create", post(create_session))
         .route("/sessions/status/:session_id", get(session_status))
+        .route("/sessions/logs/:session_id", get(session_logs))
+        .route("/sessions/stop/:session_id", get(stop_session))
         .route("/snark/create", post(create_snark))
         .route("/snark/status/:snark_id", get(snark_status))
         .route("/receipts/:session_id", get(get_receipt))
         .route("/receipts/:session_id", put(put_receipt))
+        .route("/receipts/:session_id", delete(delete_receipt))
+        .route("/sessions/:session_id", delete(delete_session))
         .route("/receipts/upload", get(get_receipt_upload))
+        .route("/user/quotas", get(user_quotas))
+        .route("/dashboard", get(dashboard::redirect))
+        .route("/dashboard/", get(dashboard::index))
+        .route("/dashboard/dashboard.js", get(dashboard::script))
+        .route("/dashboard/dashboard.css", get(dashboard::stylesheet))
+        .route("/dashboard/summary", get(dashboard::summary));
+    if let Some(token) = admin_token {
+        router = router.nest("/admin", admin::router(token));
+    }
+    let router = router
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&rate_limiter),
+            rate_limit,
+        ))
+        .layer(middleware::from_fn(check_client_version))
+        .layer(Extension(rate_limiter))
+        .layer(Extension(versions))
         .layer(Extension(prover_handle))
         .layer(Extension(url_resolver))
-        .with_state(state)
-        .layer(DefaultBodyLimit::max(256 * 1024 * 1024))
-        .layer(TraceLayer::new_for_http().on_request(
-            DefaultOnRequest::new().level(Level::TRACE), // make on_request less visible
-        ))
+        .layer(Extension(blob_options))
+        .layer(Extension(executor_limits))
+        .layer(Extension(quotas))
+        .with_state(state);
+    let router = if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(base_path, router)
+    };
+    router
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .layer(middleware::from_fn(attach_request_id))
+        .layer(middleware::map_request(fill_host_header))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(
+            TraceLayer::new_for_http()
+                // continue the client's trace if it sent a `traceparent` header
+                .make_span_with(move |request: &axum::extract::Request| {
+                    make_request_span(request, span_resolver.trusted_proxies())
+                })
+                .on_request(
+                    DefaultOnRequest::new().level(Level::TRACE), // make on_request less visible
+                ),
+        )
+        // outermost, so the request id is available to every layer above
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+pub async fn serve(listener: impl Into<Listener>, options: ServerOptions) -> anyhow::Result<()> {
+    serve_with_shutdown(listener, options, std::future::pending()).await
 }
 
-pub async fn serve(listener: TcpListener, options: ServerOptions) -> anyhow::Result<()> {
-    let local_addr = listener.local_addr().unwrap();
-    let url_resolver = Arc::new(ServerUrlResolver::new(options.server_url));
+/// Serves the Bonsai API until `signal` completes, then shuts down gracefully.
+///
+/// On shutdown new sessions are rejected with 503 and queued sessions are aborted,
+/// while running sessions get up to `shutdown_timeout` to finish. The API keeps
+/// serving in the meantime so clients can still fetch their results. Sessions still
+/// running after that are reported as failed and the function returns; in-process
+/// proofs may then still occupy blocking threads, so the runtime should be shut down
+/// without waiting for them, e.g. with [`tokio::runtime::Runtime::shutdown_timeout`].
+pub async fn serve_with_shutdown(
+    listener: impl Into<Listener>,
+    options: ServerOptions,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
     let state = Arc::new(RwLock::new(BonsaiState::new(options.ttl)));
+    serve_with_state(listener, options, signal, state).await
+}
+
+async fn serve_with_state(
+    listener: impl Into<Listener>,
+    options: ServerOptions,
+    signal: impl Future<Output = ()> + Send + 'static,
+    state: Arc<RwLock<BonsaiState>>,
+) -> anyhow::Result<()> {
+    let listener = listener.into();
+    let local_addr = listener.local_addr()?;
+    let tls_config = options
+        .tls
+        .as_ref()
+        .map(tls::server_config)
+        .transpose()
+        .context("invalid TLS configuration")?;
+    #[cfg(unix)]
+    if tls_config.is_some() && matches!(listener, Listener::Unix(_)) {
+        anyhow::bail!("TLS is not supported on Unix domain sockets");
+    }
+    let base_path = normalize_base_path(options.base_path.as_deref().unwrap_or_default())
+        .map_err(anyhow::Error::msg)?;
+    let r0vms = match &options.risc0_home {
+        Some(risc0_home) => r0vm::discover(risc0_home).with_context(|| {
+            format!(
+                "failed to discover r0vm binaries in {}",
+                risc0_home.display()
+            )
+        })?,
+        None => vec![],
+    };
+    let versions = SupportedVersions::new(r0vms);
+    info!(
+        "Supported risc0-zkvm versions: {}",
+        versions.list().join(", ")
+    );
+    let rate_limiter = Arc::new(RateLimiter::new(
+        options.rate_limits,
+        options.quotas.keys.keys().cloned(),
+        options.trusted_proxies.clone(),
+    ));
+    let url_resolver = Arc::new(
+        ServerUrlResolver::new(options.server_url)
+            .with_tls(tls_config.is_some())
+            .with_trusted_proxies(options.trusted_proxies)
+            .with_base_path(base_path.clone()),
+    );
+    let blob_options = BlobOptions {
+        max_body_size: options.max_body_size,
+        spill_threshold: options.spill_threshold,
+        spill_dir: options.spill_dir.unwrap_or_else(std::env::temp_dir),
+    };
+    let executor_limits = ExecutorLimits {
+        segment_limit_po2: options.segment_limit_po2,
+        max_cycles: options.max_cycles,
+        timeout: options.session_timeout,
+    }
+    .with_overrides(None, None, None)
+    .context("invalid executor limits")?;
+
+    if options.workers == 0 {
+        anyhow::bail!("at least one prover worker is required");
+    }
+    let control = ProverControl::new(ProverSettings {
+        paused: false,
+        workers: options.workers,
+    });
 
     let (sender, receiver) = mpsc::channel(options.channel_buffer_size);
-    let mut prover = Prover::new(receiver, Arc::clone(&state));
+    info!("Prover backend: {:?}", options.backend);
+    let prover = Prover::new(
+        Arc::clone(&state),
+        options.backend.build(),
+        options.session_log_capacity,
+        control.clone(),
+    );
 
-    let prover_handle = ProverHandle { sender };
+    let prover_handle = ProverHandle {
+        sender,
+        control: control.clone(),
+    };
 
-    tokio::spawn(async move { prover.run().await });
+    tokio::spawn(async move { prover.run(receiver).await });
 
     // Start cleanup task
     let cleanup_state = Arc::clone(&state);
     let cleanup_interval = options.cleanup_interval;
+    let cleanup_limiter = Arc::clone(&rate_limiter);
     tokio::spawn(async move {
         let mut interval = time::interval(cleanup_interval);
         // Skip the first tick to avoid immediate cleanup on startup
@@ -99,28 +331,119 @@ pub async fn serve(listener: TcpListener, options: ServerOptions) -> anyhow::Res
         loop {
             interval.tick().await;
             if let Ok(mut state) = cleanup_state.write() {
-                state.cleanup_expired();
-                info!("Cleaned up expired entries");
+                let evicted = state.cleanup_expired();
+                info!("Cleaned up {evicted} expired entries");
             }
+            cleanup_limiter.prune();
         }
     });
 
-    info!("Bonsai started on {local_addr}");
+    let shutdown_state = Arc::clone(&state);
+    let shutdown_timeout = options.shutdown_timeout;
+    let shutdown = async move {
+        signal.await;
+        info!("Shutting down, no longer accepting sessions");
+        control.shutdown();
+        if let Ok(mut state) = shutdown_state.write() {
+            let aborted = state.drain_queued();
+            info!("Aborted {aborted} queued sessions");
+        }
+        if time::timeout(shutdown_timeout, control.wait_idle())
+            .await
+            .is_err()
+        {
+            // tell clients their sessions will not finish; in-process proofs cannot be
+            // interrupted, so the caller must not wait for them when exiting
+            if let Ok(mut state) = shutdown_state.write() {
+                let failed = state.fail_running("server shut down before the session finished");
+                warn!("Failed {failed} sessions still running after {shutdown_timeout:?}");
+            }
+        }
+    };
 
-    axum::serve(listener, app(state, prover_handle, url_resolver))
-        .await
-        .context(format!("failed to serve Bonsai API on {local_addr}"))
+    let router = app(
+        state,
+        prover_handle,
+        url_resolver,
+        blob_options,
+        executor_limits,
+        Arc::new(options.quotas),
+        rate_limiter,
+        versions,
+        options.admin_token,
+        &base_path,
+    );
+
+    match (listener, tls_config) {
+        (Listener::Tcp(listener), Some(config)) => {
+            info!("Bonsai started on https://{local_addr}");
+            let handle = axum_server::Handle::new();
+            let shutdown_handle = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                shutdown_handle.graceful_shutdown(None);
+            });
+            axum_server::from_tcp_rustls(
+                listener.into_std()?,
+                RustlsConfig::from_config(Arc::new(config)),
+            )
+            .handle(handle)
+            .serve(router.into_make_service_with_connect_info::<Peer>())
+            .await
+        }
+        (Listener::Tcp(listener), None) => {
+            info!("Bonsai started on {local_addr}");
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<Peer>(),
+            )
+            .with_graceful_shutdown(shutdown)
+            .await
+        }
+        #[cfg(unix)]
+        (Listener::Unix(socket), _) => {
+            info!("Bonsai started on {local_addr}");
+            socket.serve(router, shutdown).await
+        }
+    }
+    .context(format!("failed to serve Bonsai API on {local_addr}"))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{serve, state::SessionStatus, ServerOptions};
+    use crate::{serve, serve_with_shutdown, state::SessionStatus, ServerOptions};
     use anyhow::{bail, Result};
     use risc0_zkvm::compute_image_id;
     use std::time::Duration;
     use tokio::net::TcpListener;
     use url::Url;
 
+    fn test_options(url: Url) -> ServerOptions {
+        ServerOptions {
+            server_url: Some(url),
+            ttl: Duration::from_secs(3600), // 1 hour for tests
+            channel_buffer_size: 8,
+            cleanup_interval: Duration::from_secs(60), // 60 seconds for tests
+            max_body_size: 256 * 1024 * 1024,
+            spill_threshold: 16 * 1024 * 1024,
+            spill_dir: None,
+            segment_limit_po2: 20,
+            max_cycles: None,
+            session_timeout: None,
+            session_log_capacity: 1024 * 1024,
+            workers: 1,
+            admin_token: None,
+            shutdown_timeout: Duration::from_secs(10),
+            tls: None,
+            trusted_proxies: Default::default(),
+            base_path: None,
+            quotas: Default::default(),
+            rate_limits: Default::default(),
+            risc0_home: None,
+            backend: Default::default(),
+        }
+    }
+
     async fn run_bonsai(bonsai_api_url: String, bonsai_api_key: String, elf: &[u8]) -> Result<()> {
         let client = bonsai_sdk::non_blocking::Client::from_parts(
             bonsai_api_url,
@@ -197,12 +520,7 @@ mod test {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
         let local_addr = listener.local_addr().unwrap();
         let url = Url::parse(&format!("http://{}", local_addr)).unwrap();
-        let options = ServerOptions {
-            server_url: Some(url),
-            ttl: Duration::from_secs(3600), // 1 hour for tests
-            channel_buffer_size: 8,
-            cleanup_interval: Duration::from_secs(60), // 60 seconds for tests
-        };
+        let options = test_options(url);
         let local_bonsai_handle = tokio::spawn(async move { serve(listener, options).await });
 
         // wait for the service to be up
@@ -218,4 +536,498 @@ mod test {
 
         local_bonsai_handle.abort();
     }
+
+    #[tokio::test]
+    async fn shutdown_on_signal() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(serve_with_shutdown(listener, test_options(url), async {
+            let _ = shutdown_rx.await;
+        }));
+
+        shutdown_tx.send(()).unwrap();
+        // with no sessions running the server stops right away
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("server did not shut down")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_fails_sessions_running_past_the_deadline() {
+        use crate::state::BonsaiState;
+        use std::sync::{Arc, RwLock};
+
+        let script = crate::MockScript {
+            rules: vec![crate::MockRule {
+                hang: true,
+                ..Default::default()
+            }],
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = ServerOptions {
+            backend: crate::Backend::Mock(script),
+            shutdown_timeout: Duration::from_millis(200),
+            ..test_options(Url::parse(&url).unwrap())
+        };
+        let state = Arc::new(RwLock::new(BonsaiState::new(options.ttl)));
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(crate::serve_with_state(
+            listener,
+            options,
+            async {
+                let _ = shutdown_rx.await;
+            },
+            Arc::clone(&state),
+        ));
+        let client = bonsai_sdk::non_blocking::Client::from_parts(
+            url.clone(),
+            "key".to_string(),
+            risc0_zkvm::VERSION,
+        )
+        .unwrap();
+        client.upload_img("image", b"elf".to_vec()).await.unwrap();
+        let input_id = client.upload_input(b"input".to_vec()).await.unwrap();
+        let session = client
+            .create_session("image".to_string(), input_id, vec![], false)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .expect("server did not shut down at the deadline")
+            .unwrap()
+            .unwrap();
+        let state = state.read().unwrap();
+        let info = state.get_session(&session.uuid).unwrap();
+        assert_eq!(info.status, SessionStatus::Failed);
+        assert!(info.error_msg.as_deref().unwrap().contains("shut down"));
+    }
+
+    #[tokio::test]
+    async fn quotas_are_enforced_per_api_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let mut quotas = crate::Quotas::default();
+        quotas.default.stored_bytes = Some(4);
+        quotas.keys.insert(
+            "big".to_string(),
+            crate::Quota {
+                stored_bytes: Some(1024),
+                concurrent_sessions: Some(3),
+                ..Default::default()
+            },
+        );
+        let options = ServerOptions {
+            quotas,
+            ..test_options(Url::parse(&url).unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+        let client = |key: &str| {
+            bonsai_sdk::non_blocking::Client::from_parts(
+                url.clone(),
+                key.to_string(),
+                risc0_zkvm::VERSION,
+            )
+            .unwrap()
+        };
+
+        let small = client("small");
+        small.upload_input(vec![1, 2, 3]).await.unwrap();
+        // 3 of 4 bytes are in use
+        assert!(small.upload_input(vec![4, 5]).await.is_err());
+
+        let big = client("big");
+        big.upload_input(vec![0; 512]).await.unwrap();
+        let quotas = big.quotas().await.unwrap();
+        assert_eq!(quotas.concurrent_proofs, 3);
+        assert_eq!(quotas.exec_cycle_limit, i64::MAX);
+        assert_eq!(quotas.cycle_usage, 0);
+    }
+
+    #[tokio::test]
+    async fn mock_backend_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = ServerOptions {
+            backend: crate::Backend::Mock(Default::default()),
+            ..test_options(Url::parse(&url).unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+        let client = bonsai_sdk::non_blocking::Client::from_parts(
+            url.clone(),
+            "key".to_string(),
+            risc0_zkvm::VERSION,
+        )
+        .unwrap();
+        client
+            .upload_img("mock-image", b"elf".to_vec())
+            .await
+            .unwrap();
+        let input_id = client.upload_input(vec![1, 2, 3]).await.unwrap();
+
+        for execute_only in [false, true] {
+            let session = client
+                .create_session(
+                    "mock-image".to_string(),
+                    input_id.clone(),
+                    vec![],
+                    execute_only,
+                )
+                .await
+                .unwrap();
+            let res = finished(&client, &session).await;
+            assert_eq!(res.status, SessionStatus::Succeeded.to_string());
+            assert!(res.stats.is_some());
+            match res.receipt_url {
+                // the mock receipt is the session's input
+                Some(receipt_url) => {
+                    assert!(!execute_only);
+                    assert_eq!(client.download(&receipt_url).await.unwrap(), [1, 2, 3]);
+                }
+                None => assert!(execute_only),
+            }
+        }
+    }
+
+    /// Polls the status of `session` until it is no longer running.
+    async fn finished(
+        client: &bonsai_sdk::non_blocking::Client,
+        session: &bonsai_sdk::non_blocking::SessionId,
+    ) -> bonsai_sdk::responses::SessionStatusRes {
+        loop {
+            let res = session.status(client).await.unwrap();
+            if res.status != SessionStatus::Running.to_string() {
+                return res;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_mock_sessions() {
+        use risc0_zkvm::sha::{Impl, Sha256};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let receipt_path = dir.path().join("receipt.bin");
+        std::fs::write(&receipt_path, b"canned receipt").unwrap();
+        let script = crate::MockScript {
+            rules: vec![
+                crate::MockRule {
+                    sessions: Some("..=2".parse().unwrap()),
+                    error_msg: Some("injected failure".to_string()),
+                    ..Default::default()
+                },
+                crate::MockRule {
+                    image_id: Some("canned".to_string()),
+                    delay_ms: Some(50),
+                    receipt: Some(receipt_path),
+                    ..Default::default()
+                },
+                crate::MockRule {
+                    input_sha256: Some(hex::encode(Impl::hash_bytes(b"hang").as_bytes())),
+                    hang: true,
+                    ..Default::default()
+                },
+            ],
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = ServerOptions {
+            backend: crate::Backend::Mock(script),
+            session_timeout: Some(Duration::from_millis(200)),
+            ..test_options(Url::parse(&url).unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+        let client = bonsai_sdk::non_blocking::Client::from_parts(
+            url.clone(),
+            "key".to_string(),
+            risc0_zkvm::VERSION,
+        )
+        .unwrap();
+        for image_id in ["plain", "canned"] {
+            client.upload_img(image_id, b"elf".to_vec()).await.unwrap();
+        }
+        let run = |image_id: &str, input: &[u8]| {
+            let (client, image_id, input) = (&client, image_id.to_string(), input.to_vec());
+            async move {
+                let input_id = client.upload_input(input).await.unwrap();
+                let session = client
+                    .create_session(image_id, input_id, vec![], false)
+                    .await
+                    .unwrap();
+                finished(client, &session).await
+            }
+        };
+
+        // the first two sessions fail whatever they prove, as when retrying
+        for _ in 0..2 {
+            let res = run("canned", b"input").await;
+            assert_eq!(res.status, SessionStatus::Failed.to_string());
+            assert_eq!(res.error_msg.as_deref(), Some("injected failure"));
+        }
+        let res = run("canned", b"input").await;
+        assert_eq!(res.status, SessionStatus::Succeeded.to_string());
+        let receipt = client.download(&res.receipt_url.unwrap()).await.unwrap();
+        assert_eq!(receipt, b"canned receipt");
+
+        let res = run("plain", b"hang").await;
+        assert_eq!(res.status, SessionStatus::TimedOut.to_string());
+        assert!(res.error_msg.unwrap().contains("timed out"));
+
+        let res = run("plain", b"input").await;
+        let receipt = client.download(&res.receipt_url.unwrap()).await.unwrap();
+        assert_eq!(receipt, b"input");
+    }
+
+    #[tokio::test]
+    async fn aborted_session_frees_its_worker() {
+        let script = crate::MockScript {
+            rules: vec![crate::MockRule {
+                sessions: Some("1".parse().unwrap()),
+                hang: true,
+                ..Default::default()
+            }],
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let options = ServerOptions {
+            backend: crate::Backend::Mock(script),
+            ..test_options(Url::parse(&url).unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+        let client = bonsai_sdk::non_blocking::Client::from_parts(
+            url.clone(),
+            "key".to_string(),
+            risc0_zkvm::VERSION,
+        )
+        .unwrap();
+        client.upload_img("image", b"elf".to_vec()).await.unwrap();
+        let input_id = client.upload_input(b"input".to_vec()).await.unwrap();
+        let create = || client.create_session("image".to_string(), input_id.clone(), vec![], false);
+        let hanging = create().await.unwrap();
+        let queued = create().await.unwrap();
+
+        // the only worker is taken until the hanging proof has actually stopped
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        hanging.stop(&client).await.unwrap();
+        let res = finished(&client, &hanging).await;
+        assert_eq!(res.status, SessionStatus::Aborted.to_string());
+        let res = tokio::time::timeout(Duration::from_secs(5), finished(&client, &queued))
+            .await
+            .unwrap();
+        assert_eq!(res.status, SessionStatus::Succeeded.to_string());
+    }
+
+    #[tokio::test]
+    async fn tls_requires_client_certificate() {
+        use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+        use rustls::{
+            pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+            ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+        };
+        use std::{
+            io::{Read, Write},
+            sync::Arc,
+        };
+
+        // a test CA issuing the server certificate and a client certificate
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let issue = |purpose| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            params.extended_key_usages = vec![purpose];
+            let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+            (cert, key)
+        };
+        let (server_cert, server_key) = issue(ExtendedKeyUsagePurpose::ServerAuth);
+        let (client_cert, client_key) = issue(ExtendedKeyUsagePurpose::ClientAuth);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let write = |name: &str, pem: String| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, pem).unwrap();
+            path
+        };
+        let tls = crate::TlsOptions {
+            cert_path: write("server.crt", server_cert.pem()),
+            key_path: write("server.key", server_key.serialize_pem()),
+            client_ca_path: Some(write("ca.crt", ca.pem())),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = ServerOptions {
+            tls: Some(tls),
+            ..test_options(Url::parse(&format!("https://{addr}")).unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let with_cert = builder
+            .clone()
+            .with_client_auth_cert(
+                vec![CertificateDer::from(client_cert.der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client_key.serialize_der())),
+            )
+            .unwrap();
+        let without_cert = builder.with_no_client_auth();
+
+        // a blocking GET /health, returning the response or the TLS error
+        let health = |config: ClientConfig| {
+            tokio::task::spawn_blocking(move || {
+                let server_name = ServerName::try_from("localhost").unwrap();
+                let conn = ClientConnection::new(Arc::new(config), server_name).unwrap();
+                let sock = std::net::TcpStream::connect(addr).unwrap();
+                let mut stream = StreamOwned::new(conn, sock);
+                stream.write_all(
+                    b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response)?;
+                Ok::<_, std::io::Error>(response)
+            })
+        };
+        let response = health(with_cert).await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        // with TLS 1.3 the server only rejects the certificate after the handshake
+        let err = health(without_cert).await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("CertificateRequired"), "{err}");
+    }
+
+    /// Sends a plain HTTP/1.1 GET request, returning the raw response.
+    async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request =
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn reject_incompatible_client_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener, test_options(Url::parse(&url).unwrap())));
+        let client = |version: &str| {
+            bonsai_sdk::non_blocking::Client::from_parts(url.clone(), "key".to_string(), version)
+                .unwrap()
+        };
+
+        let old = client("0.1.0");
+        // the supported versions can always be queried
+        let info = old.version().await.unwrap();
+        assert_eq!(info.risc0_zkvm, vec![risc0_zkvm::VERSION.to_string()]);
+        let err = old.upload_input(vec![1, 2, 3]).await.unwrap_err();
+        assert!(err.to_string().contains("UNSUPPORTED_VERSION"), "{err}");
+
+        client(risc0_zkvm::VERSION)
+            .upload_input(vec![1, 2, 3])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_under_base_path() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = ServerOptions {
+            server_url: None,
+            base_path: Some("/bonsai/".to_string()),
+            ..test_options(Url::parse("http://unused").unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+
+        let response = get(addr, "/bonsai/resolved-server-url").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""resolved_server_url":"http://localhost/bonsai/""#));
+        assert!(get(addr, "/health").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_status_polling() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut rate_limits = crate::RateLimits::default();
+        rate_limits.groups.insert(
+            crate::RouteGroup::Status,
+            crate::RateLimit {
+                per_second: 0.1,
+                burst: Some(1),
+            },
+        );
+        let options = ServerOptions {
+            server_url: None,
+            base_path: Some("/bonsai".to_string()),
+            rate_limits,
+            ..test_options(Url::parse("http://unused").unwrap())
+        };
+        tokio::spawn(serve(listener, options));
+
+        let response = get(addr, "/bonsai/sessions/status/unknown").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+        let response = get(addr, "/bonsai/sessions/status/unknown").await;
+        assert!(response.starts_with("HTTP/1.1 429"), "{response}");
+        assert!(response.contains("retry-after: 10"), "{response}");
+        // other route groups have their own limits
+        assert!(get(addr, "/bonsai/sessions")
+            .await
+            .starts_with("HTTP/1.1 200"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serve_on_unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("bonsai.sock");
+        let listener = crate::Listener::bind(&format!("unix:{}", path.display()))
+            .await
+            .unwrap();
+        let options = ServerOptions {
+            server_url: None,
+            ..test_options(Url::parse("http://unused").unwrap())
+        };
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(serve_with_shutdown(listener, options, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        // URLs handed to clients are built from the Host header they send
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /resolved-server-url HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""resolved_server_url":"http://localhost/""#));
+
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("server did not shut down")
+            .unwrap()
+            .unwrap();
+        assert!(!path.exists());
+    }
 }
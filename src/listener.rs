@@ -0,0 +1,214 @@
+use axum::{extract::connect_info::Connected, serve::IncomingStream};
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+};
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use std::{future::Future, path::PathBuf, time::Duration};
+#[cfg(unix)]
+use tracing::{debug, warn};
+
+/// Prefix of listen addresses naming a Unix domain socket, e.g. `unix:/run/bonsai.sock`.
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// Socket the server accepts connections on.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+impl Listener {
+    /// Binds `address`, which is either a TCP socket address such as `127.0.0.1:8080`
+    /// or `unix:<path>` for a Unix domain socket.
+    pub async fn bind(address: &str) -> io::Result<Self> {
+        match address.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => Ok(Self::Unix(UnixSocket::bind(path.into())?)),
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+            None => Ok(Self::Tcp(TcpListener::bind(address).await?)),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<LocalAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().map(LocalAddr::Tcp),
+            #[cfg(unix)]
+            Self::Unix(socket) => Ok(LocalAddr::Unix(socket.path.clone())),
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Self::Tcp(listener)
+    }
+}
+
+/// Address a [`Listener`] is bound to, displayed in the same form [`Listener::bind`] accepts.
+#[derive(Debug, Clone)]
+pub enum LocalAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for LocalAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "{UNIX_PREFIX}{}", path.display()),
+        }
+    }
+}
+
+/// The directly connected client, available to handlers as `ConnectInfo<Peer>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Peer {
+    Ip(IpAddr),
+    Unix,
+}
+
+impl Connected<IncomingStream<'_>> for Peer {
+    fn connect_info(target: IncomingStream<'_>) -> Self {
+        Self::Ip(target.remote_addr().ip())
+    }
+}
+
+impl Connected<SocketAddr> for Peer {
+    fn connect_info(remote_addr: SocketAddr) -> Self {
+        Self::Ip(remote_addr.ip())
+    }
+}
+
+/// A bound Unix domain socket; the socket file is removed when it is dropped.
+#[cfg(unix)]
+pub struct UnixSocket {
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    fn bind(path: PathBuf) -> io::Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        // A socket file left behind by an instance that did not shut down cleanly
+        // would make the bind fail. Only remove it if nobody is listening on it.
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if metadata.file_type().is_socket()
+                && std::os::unix::net::UnixStream::connect(&path).is_err()
+            {
+                debug!("Removing stale socket {}", path.display());
+                std::fs::remove_file(&path)?;
+            }
+        }
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+
+    /// Serves `router` on every accepted connection until `signal` completes,
+    /// then waits for open connections to finish.
+    pub(crate) async fn serve(
+        self,
+        router: axum::Router,
+        signal: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        use axum::{extract::ConnectInfo, Extension};
+        use hyper_util::{
+            rt::{TokioExecutor, TokioIo},
+            server::{conn::auto::Builder, graceful::GracefulShutdown},
+            service::TowerToHyperService,
+        };
+
+        let router = router.layer(Extension(ConnectInfo(Peer::Unix)));
+        let graceful = GracefulShutdown::new();
+        let builder = Builder::new(TokioExecutor::new());
+        tokio::pin!(signal);
+        loop {
+            let stream = tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        // e.g. too many open files; back off instead of spinning
+                        warn!("Failed to accept connection: {err}");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                },
+                _ = &mut signal => break,
+            };
+            let connection = builder
+                .serve_connection_with_upgrades(
+                    TokioIo::new(stream),
+                    TowerToHyperService::new(router.clone()),
+                )
+                .into_owned();
+            let connection = graceful.watch(connection);
+            tokio::spawn(async move {
+                if let Err(err) = connection.await {
+                    debug!("Connection closed with error: {err}");
+                }
+            });
+        }
+        graceful.shutdown().await;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_bind_unix_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bonsai.sock");
+        let address = format!("unix:{}", path.display());
+
+        let listener = Listener::bind(&address).await.unwrap();
+        assert_eq!(listener.local_addr().unwrap().to_string(), address);
+
+        // an instance is still listening
+        assert!(Listener::bind(&address).await.is_err());
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bonsai.sock");
+        // leaves the socket file behind without a listener
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        assert!(Listener::bind(&format!("unix:{}", path.display()))
+            .await
+            .is_ok());
+
+        // regular files are never removed
+        let file = dir.path().join("not-a-socket");
+        std::fs::write(&file, b"data").unwrap();
+        assert!(Listener::bind(&format!("unix:{}", file.display()))
+            .await
+            .is_err());
+        assert!(file.exists());
+    }
+}
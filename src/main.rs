@@ -1,7 +1,9 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bonsai_local::{config::Config, Listener};
 use clap::Parser;
-use opentelemetry::{trace::TracerProvider, KeyValue};
+use opentelemetry::{global, trace::TracerProvider, KeyValue};
 use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
     trace::{RandomIdGenerator, Sampler, SdkTracerProvider},
     Resource,
 };
@@ -9,58 +11,39 @@ use opentelemetry_semantic_conventions::{
     attribute::{SERVICE_NAME, SERVICE_VERSION},
     SCHEMA_URL,
 };
-use std::{env, time::Duration};
-use tokio::net::TcpListener;
-use tracing::debug;
+use std::{path::PathBuf, time::Duration};
+use tracing::{debug, info};
 use tracing_opentelemetry::OpenTelemetryLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use url::Url;
 
 #[derive(Parser, Debug)]
 #[command(name = "bonsai-local")]
 #[command(about = "Local Bonsai REST API Server", long_about = None)]
 struct Args {
-    /// Server URL (must be http:// or https://)
-    #[arg(long, value_parser = validate_url)]
-    server_url: Option<Url>,
+    /// Configuration file (.toml, .yaml or .yml); BONSAI_* environment variables and
+    /// command line options take precedence over its values
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
 
-    /// Address to listen on (e.g., "127.0.0.1:8080", "0.0.0.0:8080")
-    #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDRESS")]
-    listen_address: String,
-
-    /// Time-to-live for cached entries in seconds (default: 14400 = 4 hours)
-    #[arg(long, default_value = "14400", value_name = "SECONDS")]
-    ttl: u64,
-
-    /// Channel buffer size for prover queue
-    #[arg(long, default_value = "8", value_name = "SIZE")]
-    channel_buffer_size: usize,
-
-    /// Cleanup interval in seconds (default: 60)
-    #[arg(long, default_value = "60", value_name = "SECONDS")]
-    cleanup_interval: u64,
-
-    /// Required r0vm version (format: <major>.<minor>, e.g., "1.0", "1.2")
-    #[arg(long, value_name = "VERSION")]
-    r0vm_version: Option<String>,
+    #[command(flatten)]
+    options: Config,
 }
 
-fn validate_url(s: &str) -> Result<Url, String> {
-    let url = Url::parse(s).map_err(|e| format!("Invalid URL: {e}"))?;
-
-    match url.scheme() {
-        "http" | "https" => Ok(url),
-        _ => Err("URL must use http:// or https:// scheme".to_string()),
-    }
+fn main() -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(run());
+    // proofs still running after the shutdown timeout hold blocking threads that
+    // cannot be interrupted; dropping the runtime would wait for them
+    runtime.shutdown_timeout(Duration::from_secs(1));
+    result
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+async fn run() -> Result<()> {
     let args = Args::parse();
+    let config = Config::load(args.config.as_deref(), args.options)?;
+    let options = config.server_options()?;
 
-    let otel_enabled = env::var("BONSAI_OTEL_ENABLE")
-        .map(|v| v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false);
+    let otel_enabled = config.otel_enable();
     let builder = tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
@@ -68,6 +51,8 @@ async fn main() -> Result<()> {
         )
         .with(tracing_subscriber::fmt::layer());
     let shutdown_fn = if otel_enabled {
+        // accept W3C `traceparent`/`tracestate` headers from clients
+        global::set_text_map_propagator(TraceContextPropagator::new());
         let tracer_provider = init_tracer_provider();
         builder
             .with(OpenTelemetryLayer::new(tracer_provider.tracer("bonsai")))
@@ -85,23 +70,44 @@ async fn main() -> Result<()> {
     debug!("Docker check passed");
 
     // Check r0vm version if specified
-    if let Some(ref required_version) = args.r0vm_version {
+    if let Some(ref required_version) = config.r0vm_version {
         bonsai_local::version::check_r0vm_version(required_version)?;
         debug!("r0vm version check passed: {}", required_version);
     }
 
-    let listener = TcpListener::bind(&args.listen_address).await?;
-    let options = bonsai_local::ServerOptions {
-        server_url: args.server_url,
-        ttl: Duration::from_secs(args.ttl),
-        channel_buffer_size: args.channel_buffer_size,
-        cleanup_interval: Duration::from_secs(args.cleanup_interval),
-    };
-    bonsai_local::serve(listener, options).await?;
+    let listener = Listener::bind(config.listen_address())
+        .await
+        .with_context(|| format!("failed to listen on {}", config.listen_address()))?;
+    let result = bonsai_local::serve_with_shutdown(listener, options, shutdown_signal()).await;
+    // flush pending spans even if the server failed
     if let Some(f) = shutdown_fn {
         f()
     }
-    Ok(())
+    result
+}
+
+/// Completes on SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
 }
 
 fn init_tracer_provider() -> SdkTracerProvider {
@@ -0,0 +1,299 @@
+use futures_util::{future, future::BoxFuture, FutureExt};
+use risc0_zkvm::{
+    sha::{Impl, Sha256},
+    SessionStats,
+};
+use serde::Deserialize;
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    backend::{session_stats, stopped, Job, Proof, ProverBackend},
+    error::Error,
+};
+
+/// Scripted outcomes of the mock backend, for testing how clients handle failures.
+///
+/// The first rule matching a session decides what happens to it; sessions no rule
+/// matches succeed at once.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockScript {
+    pub rules: Vec<MockRule>,
+}
+
+/// A rule of a [`MockScript`]. A rule without conditions matches every session, and
+/// one without outcomes lets the sessions it matches succeed right away.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockRule {
+    /// Matches sessions proving this image
+    pub image_id: Option<String>,
+    /// Matches sessions whose input has this hex encoded SHA-256 digest
+    pub input_sha256: Option<String>,
+    /// Matches sessions by the order the mock runs them in, counting from 1
+    pub sessions: Option<SessionRange>,
+    /// Waits this many milliseconds before the outcome
+    pub delay_ms: Option<u64>,
+    /// Never finishes; the session runs until it is stopped or times out
+    pub hang: bool,
+    /// Fails the session, reporting this message as its `error_msg`
+    pub error_msg: Option<String>,
+    /// Succeeds with the contents of this file as the receipt instead of the input
+    pub receipt: Option<PathBuf>,
+}
+
+impl MockRule {
+    fn matches(&self, image_id: &str, input_sha256: &str, session: u64) -> bool {
+        self.image_id.as_deref().is_none_or(|id| id == image_id)
+            && self
+                .input_sha256
+                .as_deref()
+                .is_none_or(|digest| digest.eq_ignore_ascii_case(input_sha256))
+            && self
+                .sessions
+                .is_none_or(|sessions| sessions.contains(session))
+    }
+}
+
+impl MockScript {
+    /// Checks that the outcomes of every rule fit together.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            let outcomes = [rule.hang, rule.error_msg.is_some(), rule.receipt.is_some()];
+            if outcomes.into_iter().filter(|&outcome| outcome).count() > 1 {
+                return Err(format!(
+                    "mock rule {index}: hang, error_msg and receipt exclude each other"
+                ));
+            }
+            if let Some(digest) = &rule.input_sha256 {
+                if digest.len() != 64 || hex::decode(digest).is_err() {
+                    return Err(format!(
+                        "mock rule {index}: input_sha256 must be 64 hex digits, got {digest:?}"
+                    ));
+                }
+            }
+            if let Some(path) = rule.receipt.as_ref().filter(|path| !path.is_file()) {
+                return Err(format!(
+                    "mock rule {index}: receipt {} is not an existing file",
+                    path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Inclusive range of session numbers, written as `3`, `"1..=2"`, `"1..3"`, `"4.."`
+/// or `"..3"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "SessionRangeSpec")]
+pub struct SessionRange {
+    pub first: u64,
+    /// Unbounded if `None`
+    pub last: Option<u64>,
+}
+
+impl SessionRange {
+    fn contains(&self, session: u64) -> bool {
+        session >= self.first && self.last.is_none_or(|last| session <= last)
+    }
+}
+
+impl FromStr for SessionRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid session range {s:?}, expected e.g. 3, 1..=2 or 4..");
+        let parse = |n: &str| n.trim().parse::<u64>().map_err(|_| invalid());
+        let Some((first, last)) = s.split_once("..") else {
+            let session = parse(s)?;
+            return Ok(Self {
+                first: session,
+                last: Some(session),
+            });
+        };
+        let first = match first.trim() {
+            "" => 1,
+            first => parse(first)?,
+        };
+        let last = match last.strip_prefix('=') {
+            Some(last) => Some(parse(last)?),
+            None if last.trim().is_empty() => None,
+            None => Some(parse(last)?.checked_sub(1).ok_or_else(invalid)?),
+        };
+        Ok(Self { first, last })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SessionRangeSpec {
+    Session(u64),
+    Range(String),
+}
+
+impl TryFrom<SessionRangeSpec> for SessionRange {
+    type Error = String;
+
+    fn try_from(spec: SessionRangeSpec) -> Result<Self, Self::Error> {
+        match spec {
+            SessionRangeSpec::Session(session) => Ok(Self {
+                first: session,
+                last: Some(session),
+            }),
+            SessionRangeSpec::Range(range) => range.parse(),
+        }
+    }
+}
+
+/// Stands in for the zkVM when testing clients: sessions play out the [`MockScript`]
+/// and otherwise succeed at once, with zero cycles and their input as the receipt.
+pub(crate) struct MockBackend {
+    script: MockScript,
+    /// Number of sessions run so far
+    sessions: AtomicU64,
+}
+
+impl MockBackend {
+    pub(crate) fn new(script: MockScript) -> Self {
+        Self {
+            script,
+            sessions: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts the session and returns the first rule matching it.
+    fn rule_for(&self, job: &Job) -> Option<&MockRule> {
+        let session = self.sessions.fetch_add(1, Ordering::SeqCst) + 1;
+        let input_sha256 = hex::encode(Impl::hash_bytes(&job.input).as_bytes());
+        let (index, rule) = self
+            .script
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(&job.image_id, &input_sha256, session))?;
+        info!("Mock session {session} matches rule {index}");
+        Some(rule)
+    }
+
+    /// Plays out the outcome of `rule`, returning the receipt to succeed with. Delays
+    /// and hangs end early once `cancel` is cancelled.
+    async fn play(
+        rule: Option<&MockRule>,
+        input: Vec<u8>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<u8>, Error> {
+        let Some(rule) = rule else {
+            return Ok(input);
+        };
+        if let Some(delay) = rule.delay_ms {
+            tokio::select! {
+                () = tokio::time::sleep(Duration::from_millis(delay)) => {}
+                () = cancel.cancelled() => return Err(stopped()),
+            }
+        }
+        if rule.hang {
+            cancel.cancelled().await;
+            return Err(stopped());
+        }
+        if let Some(error_msg) = &rule.error_msg {
+            return Err(Error::SessionFailed(error_msg.clone()));
+        }
+        match &rule.receipt {
+            Some(path) => Ok(tokio::fs::read(path).await?),
+            None => Ok(input),
+        }
+    }
+}
+
+impl ProverBackend for MockBackend {
+    fn execute(&self, job: Job) -> BoxFuture<'_, Result<SessionStats, Error>> {
+        async move {
+            Self::play(self.rule_for(&job), vec![], &job.cancel).await?;
+            session_stats(0, 0, 0)
+        }
+        .boxed()
+    }
+
+    fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>> {
+        async move {
+            let receipt = Self::play(self.rule_for(&job), job.input, &job.cancel).await?;
+            Ok(Proof {
+                receipt,
+                stats: Some(session_stats(0, 0, 0)?),
+            })
+        }
+        .boxed()
+    }
+
+    fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        future::ready(Ok(receipt)).boxed()
+    }
+
+    fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        future::ready(Ok(receipt)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_range() {
+        let range = |s: &str| s.parse::<SessionRange>().unwrap();
+        let contains =
+            |range: SessionRange| (1..=5).filter(|&n| range.contains(n)).collect::<Vec<_>>();
+        assert_eq!(contains(range("3")), [3]);
+        assert_eq!(contains(range("1..=2")), [1, 2]);
+        assert_eq!(contains(range("1..3")), [1, 2]);
+        assert_eq!(contains(range("4..")), [4, 5]);
+        assert_eq!(contains(range("..3")), [1, 2]);
+        assert!("..0".parse::<SessionRange>().is_err());
+        assert!("first".parse::<SessionRange>().is_err());
+    }
+
+    #[test]
+    fn test_script_from_toml() {
+        let script: MockScript = toml::from_str(
+            r#"
+            [[rules]]
+            sessions = "1..=2"
+            error_msg = "prover crashed"
+
+            [[rules]]
+            image_id = "slow"
+            sessions = 3
+            delay_ms = 500
+            "#,
+        )
+        .unwrap();
+        script.validate().unwrap();
+        assert_eq!(
+            script.rules[0].sessions,
+            Some(SessionRange {
+                first: 1,
+                last: Some(2)
+            })
+        );
+        assert!(script.rules[1].matches("slow", "", 3));
+        assert!(!script.rules[1].matches("slow", "", 4));
+        assert!(!script.rules[1].matches("fast", "", 3));
+
+        let invalid = MockScript {
+            rules: vec![MockRule {
+                hang: true,
+                error_msg: Some("never reported".to_string()),
+                ..Default::default()
+            }],
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
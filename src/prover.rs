@@ -12,18 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use risc0_zkvm::Prover as _;
-use risc0_zkvm::{ExecutorEnv, LocalProver, ProveInfo, ProverOpts, Receipt, VerifierContext};
+use futures_util::future;
+use serde::Serialize;
 use std::{
     fmt,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock, RwLock,
+    },
     time::Duration,
 };
-use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinSet,
+};
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::state::SessionStatus;
-use crate::{error::Error, state::BonsaiState};
+use crate::{
+    backend::{stopped, Job, ProverBackend, SharedBackend},
+    blob::Blob,
+    error::{DisplayErrorCauses, Error},
+    r0vm::R0vm,
+    session_log::SessionLog,
+    state::{BonsaiState, Resource},
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct Task {
@@ -31,6 +45,69 @@ pub(crate) struct Task {
     pub image_id: String,
     pub input_id: String,
     pub assumptions: Vec<String>,
+    pub limits: ExecutorLimits,
+    /// Only executes the guest, reporting its stats without proving it
+    pub execute_only: bool,
+    /// Proves with this r0vm subprocess instead of the server's backend
+    pub r0vm: Option<R0vm>,
+    /// Trace context of the request that created the session
+    pub trace_context: opentelemetry::Context,
+}
+
+/// Smallest segment size accepted by the zkVM, as a power of two.
+pub(crate) const MIN_SEGMENT_LIMIT_PO2: u32 = 13;
+/// Largest segment size accepted by the zkVM, as a power of two.
+pub(crate) const MAX_SEGMENT_LIMIT_PO2: u32 = 24;
+
+/// Resource limits applied to a single session.
+///
+/// The server-wide defaults come from [`crate::ServerOptions`]; a session may
+/// tighten the cycle limit and timeout, or pick a smaller segment size.
+#[derive(Debug, Clone)]
+pub(crate) struct ExecutorLimits {
+    /// Segment size as a power of two
+    pub segment_limit_po2: u32,
+    /// Maximum number of cycles the executor may run, unlimited if `None`
+    pub max_cycles: Option<u64>,
+    /// Wall-clock limit for the whole session, unlimited if `None`.
+    ///
+    /// The session is reported as `TIMED_OUT` at once. An r0vm subprocess is killed,
+    /// but the in-process prover cannot be interrupted, so its proof keeps the
+    /// session's worker until it runs to completion.
+    pub timeout: Option<Duration>,
+}
+
+impl ExecutorLimits {
+    /// Applies per-session overrides on top of these limits.
+    ///
+    /// Segment sizes, cycle limits and timeouts can only be lowered, never raised
+    /// above the server-wide value: larger segments take more memory to prove.
+    pub(crate) fn with_overrides(
+        &self,
+        segment_limit_po2: Option<u32>,
+        max_cycles: Option<u64>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let max_po2 = self.segment_limit_po2.min(MAX_SEGMENT_LIMIT_PO2);
+        let segment_limit_po2 = segment_limit_po2.unwrap_or(self.segment_limit_po2);
+        if !(MIN_SEGMENT_LIMIT_PO2..=max_po2).contains(&segment_limit_po2) {
+            return Err(Error::InvalidRequest(format!(
+                "segment_limit_po2 must be between {MIN_SEGMENT_LIMIT_PO2} and {max_po2}, got {segment_limit_po2}"
+            )));
+        }
+        Ok(Self {
+            segment_limit_po2,
+            max_cycles: min_limit(self.max_cycles, max_cycles),
+            timeout: min_limit(self.timeout, timeout),
+        })
+    }
+}
+
+fn min_limit<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
 
 #[derive(Debug)]
@@ -48,9 +125,71 @@ impl fmt::Display for ProverMessage {
     }
 }
 
+/// Runtime settings of the prover, adjustable through the admin API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct ProverSettings {
+    /// Whether the prover has stopped picking up queued sessions
+    pub paused: bool,
+    /// Maximum number of sessions proven concurrently
+    pub workers: usize,
+}
+
+/// Shared handle to the [`ProverSettings`] followed by [`Prover::run`].
+#[derive(Clone)]
+pub(crate) struct ProverControl {
+    settings: Arc<watch::Sender<ProverSettings>>,
+    /// Number of sessions currently being run
+    active: Arc<watch::Sender<usize>>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl ProverControl {
+    pub(crate) fn new(settings: ProverSettings) -> Self {
+        Self {
+            settings: Arc::new(watch::Sender::new(settings)),
+            active: Arc::new(watch::Sender::new(0)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn settings(&self) -> ProverSettings {
+        *self.settings.borrow()
+    }
+
+    /// Changes the settings; the prover picks them up before its next task.
+    pub(crate) fn update(&self, modify: impl FnOnce(&mut ProverSettings)) {
+        self.settings.send_modify(modify);
+    }
+
+    fn subscribe(&self) -> watch::Receiver<ProverSettings> {
+        self.settings.subscribe()
+    }
+
+    /// Stops the prover from starting further sessions, for good.
+    pub(crate) fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        // wake the prover loop so it stops taking tasks
+        self.settings.send_modify(|_| {});
+    }
+
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Waits until no session is being run.
+    pub(crate) async fn wait_idle(&self) {
+        let _ = self
+            .active
+            .subscribe()
+            .wait_for(|active| *active == 0)
+            .await;
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ProverHandle {
     pub sender: mpsc::Sender<ProverMessage>,
+    pub control: ProverControl,
 }
 
 impl ProverHandle {
@@ -84,124 +223,438 @@ impl ProverHandle {
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct Prover {
-    pub(crate) receiver: mpsc::Receiver<ProverMessage>,
     pub(crate) storage: Arc<RwLock<BonsaiState>>,
+    pub(crate) backend: SharedBackend,
+    /// Maximum number of bytes of guest output kept per session
+    pub(crate) log_capacity: usize,
+    pub(crate) control: ProverControl,
 }
 
 impl Prover {
     pub(crate) fn new(
-        receiver: mpsc::Receiver<ProverMessage>,
         storage: Arc<RwLock<BonsaiState>>,
+        backend: SharedBackend,
+        log_capacity: usize,
+        control: ProverControl,
     ) -> Self {
-        Prover { receiver, storage }
+        Prover {
+            storage,
+            backend,
+            log_capacity,
+            control,
+        }
     }
 
-    pub async fn handle_message(&mut self, msg: &ProverMessage) -> Result<(), Error> {
+    pub async fn handle_message(&self, msg: &ProverMessage) -> Result<(), Error> {
         match msg {
             ProverMessage::RunSession(task) => {
-                info!("Running task...");
-                let image = self.get_image(task).await?;
-                let input = self.get_input(task).await?;
-                let receipts = self.get_receipts(task).await?;
-                let elf = image.as_slice();
-
-                let mut env = ExecutorEnv::builder();
-                for receipt in receipts {
-                    if receipt.is_empty() {
-                        continue;
-                    }
-                    let deserialized_receipt: Receipt = bincode::deserialize(&receipt)?;
-                    env.add_assumption(deserialized_receipt);
-                }
-
-                let env = env
-                    .write_slice(&input)
-                    .session_limit(None)
-                    .segment_limit_po2(20)
-                    .build()
-                    .map_err(|e| {
-                        anyhow::anyhow!("failed to build executor environment: {:?}", e)
-                    })?;
-                let receipt = self.prove(env, elf)?;
-                let receipt_bytes = bincode::serialize(&receipt.receipt)?;
-                self.storage
-                    .write()?
-                    .put_receipt(task.session_id.clone(), receipt_bytes);
-                self.storage.write()?.put_session(
-                    task.session_id.clone(),
-                    SessionStatus::Succeeded,
-                    Some(receipt.stats),
+                let span = info_span!(
+                    "session",
+                    session_id = %task.session_id,
+                    image_id = %task.image_id,
+                    segment_limit_po2 = task.limits.segment_limit_po2,
+                    user_cycles = field::Empty,
+                    total_cycles = field::Empty,
+                    segments = field::Empty,
                 );
+                span.set_parent(task.trace_context.clone());
+                self.run_session(task).instrument(span).await?;
             }
         }
 
         Ok(())
     }
 
-    fn prove(&self, env: ExecutorEnv, elf: &[u8]) -> Result<ProveInfo, Error> {
-        let prover = LocalProver::new("bonsai");
-        let prover_info = prover.prove_with_ctx(
-            env,
-            &VerifierContext::default(),
-            elf,
-            &ProverOpts::groth16(),
-        )?;
-        Ok(prover_info)
-    }
-
-    pub(crate) async fn run(&mut self) -> Result<(), Error> {
-        while let Some(msg) = self.receiver.recv().await {
-            info!("Received message: {}", &msg);
-            match self.handle_message(&msg).await {
-                Ok(_) => match &msg {
-                    ProverMessage::RunSession(task) => {
-                        info!("Task done: {:?}", task.session_id)
+    async fn run_session(&self, task: &Task) -> Result<(), Error> {
+        info!("Running task...");
+        let log = SessionLog::shared(self.log_capacity);
+        let cancel = {
+            let mut storage = self.storage.write()?;
+            let cancel = storage
+                .get_session(&task.session_id)
+                .map(|info| info.cancel.clone())
+                .unwrap_or_default();
+            if cancel.is_cancelled() {
+                info!("Session was aborted while queued");
+                return Ok(());
+            }
+            storage.mark_session_started(&task.session_id);
+            storage.put_session_log(task.session_id.clone(), Arc::clone(&log));
+            cancel
+        };
+
+        let fetched = async {
+            let image = self.get_image(task).await?;
+            let input = self.get_input(task).await?;
+            let mut assumptions = vec![];
+            for receipt in self.get_receipts(task).await? {
+                if receipt.is_empty() {
+                    continue;
+                }
+                assumptions.push(receipt.read_all().await?);
+            }
+            Ok::<_, Error>((image, input, assumptions))
+        }
+        .instrument(info_span!("fetch_from_state"))
+        .await;
+        // the session no longer needs the input and assumptions to stay in the state
+        self.storage.write()?.release(&task.session_id);
+        let (image, input, assumptions) = fetched?;
+
+        let stop = cancel.child_token();
+        let executed = Arc::new(OnceLock::new());
+        let job = Job {
+            image_id: task.image_id.clone(),
+            elf: image,
+            input,
+            assumptions,
+            limits: task.limits.clone(),
+            log,
+            cancel: stop.clone(),
+            executed: Arc::clone(&executed),
+        };
+        let backend: &dyn ProverBackend = match &task.r0vm {
+            Some(r0vm) => r0vm,
+            None => self.backend.as_ref(),
+        };
+        let proving = async {
+            if task.execute_only {
+                return Ok((None, Some(backend.execute(job).await?)));
+            }
+            let proof = backend.prove(job).await?;
+            if stop.is_cancelled() {
+                return Err(stopped());
+            }
+            let succinct = backend
+                .compress(proof.receipt)
+                .instrument(info_span!("lift_join"))
+                .await?;
+            if stop.is_cancelled() {
+                return Err(stopped());
+            }
+            let receipt = backend
+                .snark(succinct)
+                .instrument(info_span!("groth16_compress"))
+                .await?;
+            Ok((Some(receipt), proof.stats))
+        };
+        tokio::pin!(proving);
+        let deadline = async {
+            match task.limits.timeout {
+                Some(timeout) => {
+                    tokio::time::sleep(timeout).await;
+                    timeout
+                }
+                None => future::pending().await,
+            }
+        };
+        // sessions that do not succeed are still charged the cycles they used, as far
+        // as the backend reported them
+        let charge_executed = || {
+            if let Some(stats) = executed.get() {
+                let mut storage = self.storage.write()?;
+                storage.charge_cycles(&task.session_id, stats.total_cycles);
+            }
+            Ok::<_, Error>(())
+        };
+        // an in-process proof cannot be interrupted, so an aborted or timed out session
+        // keeps its worker until the proof returns rather than letting abandoned proofs
+        // pile up beyond `workers`
+        let (receipt, stats) = tokio::select! {
+            // a backend stopped by the abort fails, which must not be reported instead
+            biased;
+            () = cancel.cancelled() => {
+                info!("Session aborted, waiting for its proof to stop");
+                let _ = proving.await;
+                return charge_executed();
+            }
+            receipt = &mut proving => match receipt {
+                Ok(receipt) => receipt,
+                Err(err) => {
+                    charge_executed()?;
+                    return Err(err);
+                }
+            },
+            timeout = deadline => {
+                // report the timeout right away rather than once the proof stops
+                self.fail_session(task, &Error::SessionTimedOut(timeout))?;
+                stop.cancel();
+                info!("Session timed out, waiting for its proof to stop");
+                let _ = proving.await;
+                return charge_executed();
+            }
+        };
+        if let Some(stats) = &stats {
+            let span = Span::current();
+            span.record("user_cycles", stats.user_cycles);
+            span.record("total_cycles", stats.total_cycles);
+            span.record("segments", stats.segments);
+        }
+
+        info_span!("store_receipt").in_scope(|| {
+            let mut storage = self.storage.write()?;
+            // the session may have been aborted after proving finished
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            // the receipt counts towards the storage quota of the session's creator
+            if let Some(receipt) = receipt {
+                let tenant = storage
+                    .get_session(&task.session_id)
+                    .and_then(|info| info.tenant.clone());
+                storage.put_receipt(task.session_id.clone(), receipt.into(), tenant);
+            }
+            storage.put_session(task.session_id.clone(), SessionStatus::Succeeded, stats);
+            Ok(())
+        })
+    }
+
+    /// Takes tasks off the queue and runs up to `workers` of them concurrently,
+    /// following changes to the [`ProverSettings`].
+    pub(crate) async fn run(&self, mut receiver: mpsc::Receiver<ProverMessage>) {
+        let mut settings = self.control.subscribe();
+        let mut workers = JoinSet::new();
+        loop {
+            let ProverSettings {
+                paused,
+                workers: max_workers,
+            } = *settings.borrow_and_update();
+            let accepting =
+                !paused && !self.control.is_shutting_down() && workers.len() < max_workers;
+            tokio::select! {
+                msg = receiver.recv(), if accepting => match msg {
+                    Some(msg) => {
+                        let prover = self.clone();
+                        workers.spawn(async move { prover.process(msg).await });
                     }
+                    None => break,
                 },
-                Err(err) => {
-                    match &msg {
-                        ProverMessage::RunSession(task) => self.storage.write()?.put_session(
-                            task.session_id.clone(),
-                            SessionStatus::Failed,
-                            None,
-                        ),
-                    };
-                    error!("Task {} failed! - {:?}", msg, err)
+                Some(joined) = workers.join_next() => {
+                    if let Err(err) = joined.map_err(Error::from).and_then(|res| res) {
+                        error!("Prover worker failed: {:?}", err);
+                    }
                 }
+                Ok(()) = settings.changed() => {}
             }
+            self.control.active.send_replace(workers.len());
+        }
+        while workers.join_next().await.is_some() {}
+    }
+
+    async fn process(&self, msg: ProverMessage) -> Result<(), Error> {
+        info!("Received message: {}", &msg);
+        match self.handle_message(&msg).await {
+            Ok(_) => match &msg {
+                ProverMessage::RunSession(task) => {
+                    info!("Task done: {:?}", task.session_id)
+                }
+            },
+            Err(err) => {
+                match &msg {
+                    ProverMessage::RunSession(task) => self.fail_session(task, &err)?,
+                };
+                error!("Task {} failed! - {:?}", msg, err)
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports the session as failed with `err`, or as timed out.
+    fn fail_session(&self, task: &Task, err: &Error) -> Result<(), Error> {
+        let status = match err {
+            Error::SessionTimedOut(_) => SessionStatus::TimedOut,
+            _ => SessionStatus::Failed,
+        };
+        let mut storage = self.storage.write()?;
+        let error_msg = DisplayErrorCauses(err).to_string();
+        storage.put_session(task.session_id.clone(), status, None);
+        storage.put_session_error(&task.session_id, error_msg.clone());
+        // surface the failure next to the guest output
+        if let Some(log) = storage.get_session_log(&task.session_id) {
+            log.lock()?.append(format!("\n{error_msg}\n").as_bytes());
         }
         Ok(())
     }
 
     async fn get_image(&self, task: &Task) -> Result<Vec<u8>, Error> {
-        Ok(self
-            .storage
-            .read()?
-            .get_image(&task.image_id)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get image for ID: {:?}", task.image_id))?)
+        let image = {
+            let storage = self.storage.read()?;
+            storage
+                .get_image(&task.image_id)
+                .ok_or_else(|| storage.missing(Resource::Image, &task.image_id))?
+        };
+        Ok(image.read_all().await?)
     }
 
     async fn get_input(&self, task: &Task) -> Result<Vec<u8>, Error> {
-        Ok(self
-            .storage
-            .read()?
-            .get_input(&task.input_id)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get input for ID: {:?}", task.input_id))?)
+        let input = {
+            let storage = self.storage.read()?;
+            storage
+                .get_input(&task.input_id)
+                .ok_or_else(|| storage.missing(Resource::Input, &task.input_id))?
+        };
+        Ok(input.read_all().await?)
     }
 
-    async fn get_receipts(&self, task: &Task) -> Result<Vec<Vec<u8>>, Error> {
-        let mut assumptions: Vec<Vec<u8>> = vec![];
+    async fn get_receipts(&self, task: &Task) -> Result<Vec<Blob>, Error> {
+        let mut assumptions: Vec<Blob> = vec![];
+        let storage = self.storage.read()?;
         for receipt_id in &task.assumptions {
-            let receipt = self
-                .storage
-                .read()?
+            let receipt = storage
                 .get_receipt(receipt_id)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Failed to get input for ID: {:?}", task.input_id)
-                })?;
+                .ok_or_else(|| storage.missing(Resource::Receipt, receipt_id))?;
             assumptions.push(receipt);
         }
         Ok(assumptions)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{session_stats, Proof};
+    use futures_util::{future::BoxFuture, FutureExt};
+    use risc0_zkvm::SessionStats;
+    use tokio::sync::Notify;
+
+    /// Proves like the in-process prover: reports the executed cycles, then ignores
+    /// cancellation until released.
+    struct Uninterruptible(Arc<Notify>);
+
+    impl ProverBackend for Uninterruptible {
+        fn execute(&self, _job: Job) -> BoxFuture<'_, Result<SessionStats, Error>> {
+            unimplemented!()
+        }
+
+        fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>> {
+            async move {
+                let _ = job.executed.set(session_stats(1, 1 << 16, 1000)?);
+                self.0.notified().await;
+                Ok(Proof {
+                    receipt: job.input,
+                    stats: None,
+                })
+            }
+            .boxed()
+        }
+
+        fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+            future::ready(Ok(receipt)).boxed()
+        }
+
+        fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+            future::ready(Ok(receipt)).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_session_keeps_its_worker() {
+        let storage = Arc::new(RwLock::new(BonsaiState::new(Duration::from_secs(60))));
+        {
+            let mut storage = storage.write().unwrap();
+            storage.put_image("image".to_string(), b"elf".to_vec().into(), None);
+            storage.put_input("input".to_string(), b"input".to_vec().into(), None);
+            storage.put_session_info(
+                "session".to_string(),
+                crate::state::SessionInfo::new("image".to_string(), None),
+            );
+        }
+        let release = Arc::new(Notify::new());
+        let control = ProverControl::new(ProverSettings {
+            paused: false,
+            workers: 1,
+        });
+        let prover = Prover::new(
+            Arc::clone(&storage),
+            Arc::new(Uninterruptible(Arc::clone(&release))),
+            1024,
+            control.clone(),
+        );
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move { prover.run(receiver).await });
+        let task = Task {
+            session_id: "session".to_string(),
+            image_id: "image".to_string(),
+            input_id: "input".to_string(),
+            assumptions: vec![],
+            limits: ExecutorLimits {
+                timeout: Some(Duration::from_millis(50)),
+                ..limits()
+            },
+            execute_only: false,
+            r0vm: None,
+            trace_context: Default::default(),
+        };
+        sender.send(ProverMessage::RunSession(task)).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let status = |storage: &Arc<RwLock<BonsaiState>>| {
+            storage
+                .read()
+                .unwrap()
+                .get_session("session")
+                .unwrap()
+                .status
+        };
+        assert_eq!(status(&storage), SessionStatus::TimedOut);
+        // the proof still runs, so its worker is not free for another session
+        assert_eq!(*control.active.borrow(), 1);
+
+        release.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), control.wait_idle())
+            .await
+            .unwrap();
+        assert_eq!(status(&storage), SessionStatus::TimedOut);
+        assert!(storage.read().unwrap().get_receipt("session").is_none());
+        // the cycles executed before the timeout still count towards the budget
+        assert_eq!(storage.read().unwrap().cycles.last_day(None), 1 << 16);
+    }
+
+    fn limits() -> ExecutorLimits {
+        ExecutorLimits {
+            segment_limit_po2: 20,
+            max_cycles: Some(1_000_000),
+            timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_with_overrides_defaults() {
+        let limits = limits().with_overrides(None, None, None).unwrap();
+        assert_eq!(limits.segment_limit_po2, 20);
+        assert_eq!(limits.max_cycles, Some(1_000_000));
+        assert_eq!(limits.timeout, None);
+    }
+
+    #[test]
+    fn test_with_overrides_cannot_raise_limits() {
+        let limits = limits()
+            .with_overrides(Some(16), Some(2_000_000), Some(Duration::from_secs(30)))
+            .unwrap();
+        assert_eq!(limits.segment_limit_po2, 16);
+        assert_eq!(limits.max_cycles, Some(1_000_000));
+        assert_eq!(limits.timeout, Some(Duration::from_secs(30)));
+
+        let limits = limits.with_overrides(None, Some(500), None).unwrap();
+        assert_eq!(limits.max_cycles, Some(500));
+        // segments larger than the server default take more memory than planned for
+        assert!(matches!(
+            limits.with_overrides(Some(17), None, None),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_with_overrides_invalid_po2() {
+        assert!(matches!(
+            limits().with_overrides(Some(MIN_SEGMENT_LIMIT_PO2 - 1), None, None),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            limits().with_overrides(Some(MAX_SEGMENT_LIMIT_PO2 + 1), None, None),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Window over which `cycles_per_day` is enforced.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Limits applied to the requests of one API key; unset limits are unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    /// Sessions queued or running at the same time
+    pub concurrent_sessions: Option<usize>,
+    /// Executor cycles of the sessions finished within the last 24 hours
+    pub cycles_per_day: Option<u64>,
+    /// Total size of the stored images, inputs and receipts in bytes
+    pub stored_bytes: Option<u64>,
+    /// Executor cycles per session
+    pub max_cycles: Option<u64>,
+}
+
+impl Quota {
+    /// Returns `self` with every limit set in `overrides` replaced.
+    fn merge(self, overrides: Quota) -> Self {
+        Self {
+            concurrent_sessions: overrides.concurrent_sessions.or(self.concurrent_sessions),
+            cycles_per_day: overrides.cycles_per_day.or(self.cycles_per_day),
+            stored_bytes: overrides.stored_bytes.or(self.stored_bytes),
+            max_cycles: overrides.max_cycles.or(self.max_cycles),
+        }
+    }
+}
+
+/// Quotas of all tenants, as configured in the `[quotas]` section of the config file.
+///
+/// ```toml
+/// [quotas.default]
+/// concurrent_sessions = 2
+///
+/// [quotas.keys.ci-key]
+/// concurrent_sessions = 8
+/// cycles_per_day = 100_000_000_000
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    /// Applies to every API key, and to requests without one
+    pub default: Quota,
+    /// Per API key limits, overriding those of `default` they set
+    pub keys: HashMap<String, Quota>,
+}
+
+pub(crate) type SharedQuotas = Arc<Quotas>;
+
+impl Quotas {
+    /// Returns the quota of the tenant identified by `api_key`.
+    pub(crate) fn for_tenant(&self, api_key: Option<&str>) -> Quota {
+        match api_key.and_then(|key| self.keys.get(key)) {
+            Some(quota) => self.default.merge(*quota),
+            None => self.default,
+        }
+    }
+}
+
+/// Executor cycles consumed per tenant.
+#[derive(Debug, Default)]
+pub(crate) struct CycleLedger(HashMap<Option<String>, TenantCycles>);
+
+#[derive(Debug, Default)]
+struct TenantCycles {
+    lifetime: u64,
+    /// Cycles of the sessions finished within the last day, oldest first
+    recent: VecDeque<(Instant, u64)>,
+}
+
+impl CycleLedger {
+    pub(crate) fn record(&mut self, tenant: Option<String>, cycles: u64) {
+        let entry = self.0.entry(tenant).or_default();
+        entry.lifetime = entry.lifetime.saturating_add(cycles);
+        while entry
+            .recent
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() > DAY)
+        {
+            entry.recent.pop_front();
+        }
+        entry.recent.push_back((Instant::now(), cycles));
+    }
+
+    /// Cycles used by `tenant` within the last 24 hours.
+    pub(crate) fn last_day(&self, tenant: Option<&str>) -> u64 {
+        self.get(tenant).map_or(0, |entry| {
+            entry
+                .recent
+                .iter()
+                .filter(|(at, _)| at.elapsed() <= DAY)
+                .map(|(_, cycles)| cycles)
+                .sum()
+        })
+    }
+
+    /// Time until the oldest cycles `tenant` used within the last 24 hours leave that
+    /// window, freeing up part of its budget.
+    pub(crate) fn next_expiry(&self, tenant: Option<&str>) -> Option<Duration> {
+        let entry = self.get(tenant)?;
+        entry
+            .recent
+            .iter()
+            .find(|(at, cycles)| *cycles > 0 && at.elapsed() <= DAY)
+            .map(|(at, _)| DAY.saturating_sub(at.elapsed()))
+    }
+
+    /// Cycles used by `tenant` since the server started.
+    pub(crate) fn lifetime(&self, tenant: Option<&str>) -> u64 {
+        self.get(tenant).map_or(0, |entry| entry.lifetime)
+    }
+
+    fn get(&self, tenant: Option<&str>) -> Option<&TenantCycles> {
+        self.0.get(&tenant.map(str::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_tenant() {
+        let quotas: Quotas = toml::from_str(
+            r#"
+            [default]
+            concurrent_sessions = 2
+            stored_bytes = 1024
+
+            [keys.big]
+            concurrent_sessions = 8
+            cycles_per_day = 1_000_000
+            "#,
+        )
+        .unwrap();
+
+        let default = Quota {
+            concurrent_sessions: Some(2),
+            stored_bytes: Some(1024),
+            ..Default::default()
+        };
+        assert_eq!(quotas.for_tenant(None), default);
+        assert_eq!(quotas.for_tenant(Some("other")), default);
+        assert_eq!(
+            quotas.for_tenant(Some("big")),
+            Quota {
+                concurrent_sessions: Some(8),
+                cycles_per_day: Some(1_000_000),
+                stored_bytes: Some(1024),
+                max_cycles: None,
+            }
+        );
+
+        assert!(toml::from_str::<Quotas>("[default]\nsessions = 1").is_err());
+    }
+
+    #[test]
+    fn test_cycle_ledger() {
+        let mut ledger = CycleLedger::default();
+        ledger.record(Some("a".to_string()), 100);
+        ledger.record(Some("a".to_string()), 50);
+        ledger.record(None, 7);
+
+        assert_eq!(ledger.last_day(Some("a")), 150);
+        assert_eq!(ledger.lifetime(Some("a")), 150);
+        assert_eq!(ledger.last_day(None), 7);
+        assert_eq!(ledger.last_day(Some("b")), 0);
+        assert_eq!(ledger.lifetime(Some("b")), 0);
+
+        let expiry = ledger.next_expiry(Some("a")).unwrap();
+        assert!(expiry <= DAY && expiry > DAY - Duration::from_secs(60));
+        assert_eq!(ledger.next_expiry(Some("b")), None);
+    }
+}
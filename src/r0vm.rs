@@ -0,0 +1,566 @@
+use anyhow::anyhow;
+use futures_util::{future, future::BoxFuture, FutureExt};
+use risc0_zkvm::SessionStats;
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    backend::{session_stats, stopped, Job, Proof, ProverBackend},
+    error::Error,
+    prover::ExecutorLimits,
+    session_log::{SessionLog, SharedSessionLog},
+};
+
+/// Bytes of r0vm's output kept while proving, added to the session log on failure
+const PROVE_LOG_CAPACITY: usize = 64 * 1024;
+
+/// An `r0vm` binary of a risc0-zkvm version other than the one the server is built
+/// with, run as a subprocess.
+///
+/// r0vm's IPC protocol only talks to clients of its own version, so it is driven
+/// through its command line instead. The receipt it writes is passed on to the
+/// client as is, encoded for the client's version.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct R0vm {
+    pub version: String,
+    pub path: PathBuf,
+}
+
+impl R0vm {
+    /// Executes `elf` on `input` without proving it, streaming the guest's output to
+    /// `log`, and returns the cycle counts r0vm logged.
+    pub(crate) async fn count_cycles(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        limits: &ExecutorLimits,
+        log: SharedSessionLog,
+        cancel: &CancellationToken,
+    ) -> Result<CycleReport, Error> {
+        let (_, report) = self.run(elf, input, limits, log, cancel, false).await?;
+        Ok(report)
+    }
+
+    /// Proves `elf` on `input` to a Groth16 receipt, the kind sessions hand out.
+    ///
+    /// The guest's output was already logged when it was executed, so it only goes
+    /// to `log` if r0vm fails.
+    pub(crate) async fn prove_groth16(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        limits: &ExecutorLimits,
+        log: SharedSessionLog,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<u8>, Error> {
+        let output = SessionLog::shared(PROVE_LOG_CAPACITY);
+        match self
+            .run(elf, input, limits, output.clone(), cancel, true)
+            .await
+        {
+            Ok((receipt, _)) => receipt.ok_or_else(|| anyhow!("r0vm wrote no receipt").into()),
+            Err(err) => {
+                if let (Ok(output), Ok(mut log)) = (output.lock(), log.lock()) {
+                    log.append(output.contents().as_bytes());
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs r0vm on `elf` and `input`, proving them to a Groth16 receipt if `prove`
+    /// is set, and returns the receipt along with the cycle counts r0vm logged.
+    ///
+    /// The command line offers no cycle limit, so only the segment size of `limits`
+    /// applies. Cancelling `cancel` or dropping the future kills the subprocess.
+    async fn run(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        limits: &ExecutorLimits,
+        log: SharedSessionLog,
+        cancel: &CancellationToken,
+        prove: bool,
+    ) -> Result<(Option<Vec<u8>>, CycleReport), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let elf_path = dir.path().join("guest.elf");
+        let input_path = dir.path().join("input.bin");
+        let receipt_path = dir.path().join("receipt.bin");
+        tokio::fs::write(&elf_path, elf).await?;
+        tokio::fs::write(&input_path, input).await?;
+
+        info!(
+            "{} with r0vm {} at {}",
+            if prove { "Proving" } else { "Executing" },
+            self.version,
+            self.path.display()
+        );
+        let mut command = Command::new(&self.path);
+        command
+            .arg("--elf")
+            .arg(&elf_path)
+            .arg("--initial-input")
+            .arg(&input_path)
+            .arg("--segment-limit-po2")
+            .arg(limits.segment_limit_po2.to_string());
+        // without a receipt to write, r0vm only executes the guest
+        if prove {
+            command
+                .arg("--receipt")
+                .arg(&receipt_path)
+                .arg("--receipt-kind")
+                .arg("groth16");
+        }
+        let mut child = command
+            // makes r0vm log the session's cycles once the guest has run
+            .env("RISC0_INFO", "1")
+            .env("RUST_LOG", "info")
+            .env("NO_COLOR", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let finished = async { tokio::join!(child.wait(), pipe(stdout, &log), pipe(stderr, &log)) };
+        let (status, report) = tokio::select! {
+            (status, stdout, stderr) = finished => (status?, stdout.max(stderr)),
+            () = cancel.cancelled() => {
+                child.kill().await?;
+                return Err(stopped());
+            }
+        };
+        if !status.success() {
+            return Err(anyhow!("r0vm {} exited with {status}", self.version).into());
+        }
+        let receipt = match prove {
+            true => Some(tokio::fs::read(&receipt_path).await?),
+            false => None,
+        };
+        Ok((receipt, report))
+    }
+}
+
+/// The cycle counts r0vm logs after executing a session with `RISC0_INFO` set, as in
+/// `INFO risc0_zkvm::host::server::session: 1048576 total cycles`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct CycleReport {
+    segments: Option<usize>,
+    total_cycles: Option<u64>,
+    user_cycles: Option<u64>,
+}
+
+impl CycleReport {
+    /// Picks up the counts of a line of r0vm's output. Later lines win, so guest
+    /// output that looks like a count is overridden by r0vm's own, which follows it.
+    fn scan(&mut self, line: &str) {
+        let count = |suffix: &str| {
+            let (head, _) = line.split_once(suffix)?;
+            head.rsplit(' ').next()?.parse().ok()
+        };
+        if let Some((_, segments)) = line.split_once("number of segments: ") {
+            self.segments = segments.trim().parse().ok().or(self.segments);
+        }
+        self.total_cycles = count(" total cycles").or(self.total_cycles);
+        self.user_cycles = count(" user cycles").or(self.user_cycles);
+    }
+
+    /// Combines the reports of stdout and stderr, keeping the higher counts so guest
+    /// output on the stream r0vm does not log to cannot lower them.
+    fn max(self, other: Self) -> Self {
+        Self {
+            segments: self.segments.max(other.segments),
+            total_cycles: self.total_cycles.max(other.total_cycles),
+            user_cycles: self.user_cycles.max(other.user_cycles),
+        }
+    }
+
+    /// `None` if r0vm logged no total.
+    fn stats(self) -> Result<Option<SessionStats>, Error> {
+        self.total_cycles
+            .map(|total_cycles| {
+                session_stats(
+                    self.segments.unwrap_or_default(),
+                    total_cycles,
+                    self.user_cycles.unwrap_or_default(),
+                )
+            })
+            .transpose()
+    }
+}
+
+impl R0vm {
+    /// Executes the guest of `job`, reporting the cycles it used to `job.executed`.
+    ///
+    /// r0vm cannot be given a cycle limit, so the limit is checked once the guest has
+    /// run, and before anything is proven.
+    async fn run_executor(&self, job: &Job) -> Result<Option<SessionStats>, Error> {
+        if !job.assumptions.is_empty() {
+            return Err(anyhow!(
+                "assumptions are not supported when proving with r0vm {}",
+                self.version
+            )
+            .into());
+        }
+        let report = self
+            .count_cycles(
+                &job.elf,
+                &job.input,
+                &job.limits,
+                job.log.clone(),
+                &job.cancel,
+            )
+            .await?;
+        let stats = report.stats()?;
+        if let Some(stats) = &stats {
+            let _ = job.executed.set(stats.clone());
+        }
+        if let Some(limit) = job.limits.max_cycles {
+            let Some(stats) = &stats else {
+                return Err(anyhow!(
+                    "r0vm {} reported no cycle count, so the limit of {limit} cycles \
+                     cannot be enforced",
+                    self.version
+                )
+                .into());
+            };
+            if stats.total_cycles > limit {
+                return Err(anyhow!(
+                    "session used {} cycles, over its limit of {limit}",
+                    stats.total_cycles
+                )
+                .into());
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// r0vm proves a session straight to a Groth16 receipt, encoded for its own version,
+/// which this server cannot decode and thus passes on as is.
+impl ProverBackend for R0vm {
+    fn execute(&self, job: Job) -> BoxFuture<'_, Result<SessionStats, Error>> {
+        async move {
+            self.run_executor(&job)
+                .await?
+                .ok_or_else(|| anyhow!("r0vm {} reported no cycle count", self.version).into())
+        }
+        .boxed()
+    }
+
+    fn prove(&self, job: Job) -> BoxFuture<'_, Result<Proof, Error>> {
+        async move {
+            let stats = self.run_executor(&job).await?;
+            let receipt = self
+                .prove_groth16(&job.elf, &job.input, &job.limits, job.log, &job.cancel)
+                .await?;
+            Ok(Proof { receipt, stats })
+        }
+        .boxed()
+    }
+
+    fn compress(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        future::ready(Ok(receipt)).boxed()
+    }
+
+    fn snark(&self, receipt: Vec<u8>) -> BoxFuture<'_, Result<Vec<u8>, Error>> {
+        future::ready(Ok(receipt)).boxed()
+    }
+}
+
+/// Appends everything read from `reader` to the session log, scanning it for the
+/// cycle counts r0vm logs.
+async fn pipe(reader: Option<impl AsyncRead + Unpin>, log: &SharedSessionLog) -> CycleReport {
+    let mut report = CycleReport::default();
+    let Some(mut reader) = reader else {
+        return report;
+    };
+    let mut buf = [0; 4096];
+    // the counts are on short lines, so longer ones are cut off rather than buffered
+    let mut line = Vec::new();
+    while let Ok(len @ 1..) = reader.read(&mut buf).await {
+        if let Ok(mut log) = log.lock() {
+            log.append(&buf[..len]);
+        }
+        for &byte in &buf[..len] {
+            if byte == b'\n' {
+                report.scan(&String::from_utf8_lossy(&line));
+                line.clear();
+            } else if line.len() < 256 {
+                line.push(byte);
+            }
+        }
+    }
+    report.scan(&String::from_utf8_lossy(&line));
+    report
+}
+
+/// Finds the r0vm binaries rzup installed under `risc0_home`, e.g. `~/.risc0`, at
+/// `extensions/v<version>-<component>[-<target>]/r0vm`, ordered by version.
+///
+/// Both the `r0vm` and the older `cargo-risczero` components ship the binary; the
+/// `r0vm` one is used if a version has both.
+pub(crate) fn discover(risc0_home: &Path) -> io::Result<Vec<R0vm>> {
+    let mut found: HashMap<String, (bool, PathBuf)> = HashMap::new();
+    for entry in std::fs::read_dir(risc0_home.join("extensions"))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some((version, component)) = name.to_str().and_then(parse_extension_dir) else {
+            continue;
+        };
+        let path = entry.path().join("r0vm");
+        if !path.is_file() {
+            continue;
+        }
+        let preferred = component.starts_with("r0vm");
+        if found
+            .get(version)
+            .is_some_and(|(existing, _)| *existing || !preferred)
+        {
+            continue;
+        }
+        found.insert(version.to_string(), (preferred, path));
+    }
+    let mut r0vms: Vec<R0vm> = found
+        .into_iter()
+        .map(|(version, (_, path))| R0vm { version, path })
+        .collect();
+    r0vms.sort_by_key(|r0vm| version_key(&r0vm.version));
+    Ok(r0vms)
+}
+
+/// Splits an extension directory name such as `v1.2.0-r0vm-x86_64-unknown-linux-gnu`
+/// into its version and component.
+fn parse_extension_dir(name: &str) -> Option<(&str, &str)> {
+    let (version, component) = name.strip_prefix('v')?.split_once('-')?;
+    let mut parts = version.split('.');
+    let valid = parts.clone().count() == 3
+        && parts.all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    valid.then_some((version, component))
+}
+
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn install(home: &Path, dir: &str) -> PathBuf {
+        let dir = home.join("extensions").join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("r0vm");
+        std::fs::write(&path, b"").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_extension_dir() {
+        assert_eq!(
+            parse_extension_dir("v1.2.0-r0vm-x86_64-unknown-linux-gnu"),
+            Some(("1.2.0", "r0vm-x86_64-unknown-linux-gnu"))
+        );
+        assert_eq!(
+            parse_extension_dir("v1.0.1-cargo-risczero"),
+            Some(("1.0.1", "cargo-risczero"))
+        );
+        assert_eq!(parse_extension_dir("v1.2-r0vm"), None);
+        assert_eq!(parse_extension_dir("1.2.0-r0vm"), None);
+        assert_eq!(parse_extension_dir("vlatest-r0vm"), None);
+    }
+
+    #[test]
+    fn test_discover() {
+        let home = TempDir::new().unwrap();
+        install(
+            home.path(),
+            "v1.10.0-cargo-risczero-x86_64-unknown-linux-gnu",
+        );
+        install(
+            home.path(),
+            "v1.2.0-cargo-risczero-x86_64-unknown-linux-gnu",
+        );
+        let r0vm = install(home.path(), "v1.2.0-r0vm-x86_64-unknown-linux-gnu");
+        // rust toolchains and incomplete installs have no r0vm
+        std::fs::create_dir_all(home.path().join("extensions/v1.81.0-rust-x86_64")).unwrap();
+
+        let found = discover(home.path()).unwrap();
+        let versions: Vec<_> = found.iter().map(|r0vm| r0vm.version.as_str()).collect();
+        assert_eq!(versions, ["1.2.0", "1.10.0"]);
+        assert_eq!(found[0].path, r0vm);
+
+        assert!(discover(&home.path().join("missing")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prove_runs_subprocess() {
+        use crate::session_log::SessionLog;
+        use std::os::unix::fs::PermissionsExt;
+
+        // stands in for r0vm: prints its arguments and, when proving, copies the input
+        // to the receipt
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("r0vm");
+        std::fs::write(
+            &path,
+            "#!/bin/sh\necho \"$@\"\n[ \"$6\" = 16 ] || exit 3\n[ -z \"$7\" ] || cp \"$4\" \"$8\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let r0vm = R0vm {
+            version: "1.2.0".to_string(),
+            path,
+        };
+        let mut limits = ExecutorLimits {
+            segment_limit_po2: 16,
+            max_cycles: None,
+            timeout: None,
+        };
+        let cancel = CancellationToken::new();
+
+        let log = SessionLog::shared(1024);
+        let report = r0vm
+            .count_cycles(b"elf", b"input", &limits, log.clone(), &cancel)
+            .await
+            .unwrap();
+        assert_eq!(report, CycleReport::default());
+        let output = log.lock().unwrap().contents();
+        assert!(output.starts_with("--elf "));
+        assert!(!output.contains("--receipt"));
+
+        // the output of a successful proof is left out of the log
+        let log = SessionLog::shared(1024);
+        let receipt = r0vm
+            .prove_groth16(b"elf", b"input", &limits, log.clone(), &cancel)
+            .await
+            .unwrap();
+        assert_eq!(receipt, b"input");
+        assert_eq!(log.lock().unwrap().contents(), "");
+
+        limits.segment_limit_po2 = 20;
+        let log = SessionLog::shared(1024);
+        let err = r0vm
+            .prove_groth16(b"elf", b"input", &limits, log.clone(), &cancel)
+            .await
+            .unwrap_err();
+        assert!(format!("{}", crate::error::DisplayErrorCauses(&err)).contains("exit status: 3"));
+        assert!(log
+            .lock()
+            .unwrap()
+            .contents()
+            .contains("--receipt-kind groth16"));
+    }
+
+    #[test]
+    fn test_cycle_report() {
+        let mut report = CycleReport::default();
+        // guest output imitating the counts is overridden by r0vm's
+        report.scan("1 total cycles");
+        report.scan(
+            "2024-01-01T00:00:00Z  INFO risc0_zkvm::host::server::session: number of segments: 2",
+        );
+        report.scan(
+            "2024-01-01T00:00:00Z  INFO risc0_zkvm::host::server::session: 3000 total cycles",
+        );
+        report.scan("2024-01-01T00:00:00Z  INFO risc0_zkvm::host::server::session: 2500 user cycles (83.33%)");
+        report.scan("\t4 sha2 calls, 100 cycles, (3.33%)");
+        let stats = report.stats().unwrap().unwrap();
+        assert_eq!(
+            (stats.segments, stats.total_cycles, stats.user_cycles),
+            (2, 3000, 2500)
+        );
+
+        // nor can it lower them from the other stream
+        let mut spoofed = CycleReport::default();
+        spoofed.scan("10 total cycles");
+        assert_eq!(spoofed.max(report), report);
+        assert_eq!(CycleReport::default().stats().unwrap().map(|_| ()), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prove_enforces_cycle_limit() {
+        use crate::session_log::SessionLog;
+        use std::{os::unix::fs::PermissionsExt, sync::Arc};
+
+        // stands in for r0vm: logs the cycles it was given and, when proving, copies the
+        // input to the receipt and leaves a mark
+        let dir = TempDir::new().unwrap();
+        let proved = dir.path().join("proved");
+        let r0vm = |name: &str, cycles: Option<u64>| {
+            let path = dir.path().join(name);
+            let log = cycles
+                .map(|cycles| format!("echo \" INFO session: {cycles} total cycles\"\n"))
+                .unwrap_or_default();
+            let script = format!(
+                "#!/bin/sh\n{log}[ -z \"$7\" ] || {{ cp \"$4\" \"$8\"; touch {}; }}\n",
+                proved.display()
+            );
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            R0vm {
+                version: "1.2.0".to_string(),
+                path,
+            }
+        };
+        let prove = |r0vm: R0vm, max_cycles: Option<u64>| async move {
+            let executed = Arc::new(std::sync::OnceLock::new());
+            let job = Job {
+                image_id: "image".to_string(),
+                elf: b"elf".to_vec(),
+                input: b"input".to_vec(),
+                assumptions: vec![],
+                limits: ExecutorLimits {
+                    segment_limit_po2: 20,
+                    max_cycles,
+                    timeout: None,
+                },
+                log: SessionLog::shared(1024),
+                cancel: CancellationToken::new(),
+                executed: Arc::clone(&executed),
+            };
+            let proof = r0vm.prove(job).await;
+            (proof, executed.get().map(|stats| stats.total_cycles))
+        };
+
+        let (proof, executed) = prove(r0vm("within", Some(3000)), Some(3000)).await;
+        let proof = proof.unwrap();
+        assert_eq!(proof.receipt, b"input");
+        assert_eq!(proof.stats.map(|stats| stats.total_cycles), Some(3000));
+        assert_eq!(executed, Some(3000));
+
+        std::fs::remove_file(&proved).unwrap();
+
+        // a session over its limit is stopped before it is proven, but its cycles are
+        // still reported for charging
+        let (proof, executed) = prove(r0vm("over", Some(3001)), Some(3000)).await;
+        assert!(proof.is_err());
+        assert_eq!(executed, Some(3001));
+        assert!(!proved.exists());
+
+        // without a count a limit cannot be enforced
+        let (proof, _) = prove(r0vm("silent", None), Some(3000)).await;
+        assert!(proof.is_err());
+        assert!(!proved.exists());
+        let (proof, executed) = prove(r0vm("unlimited", None), None).await;
+        assert!(proof.unwrap().stats.is_none());
+        assert_eq!(executed, None);
+    }
+}
@@ -0,0 +1,565 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Error, forwarded, listener::Peer, routes::API_KEY_HEADER,
+    trusted_proxies::TrustedProxies,
+};
+
+/// Slowest refill rate accepted, one request every ~17 minutes
+const MIN_PER_SECOND: f64 = 0.001;
+
+/// Longest wait reported or tracked for a bucket
+const MAX_WAIT: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Buckets kept before full ones are evicted to make room for new clients
+const MAX_BUCKETS: usize = 65_536;
+
+const ROUTE_GROUPS: [RouteGroup; 5] = [
+    RouteGroup::Status,
+    RouteGroup::Create,
+    RouteGroup::Upload,
+    RouteGroup::Download,
+    RouteGroup::Other,
+];
+
+/// Routes sharing a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+    /// Polling session and SNARK status and session logs
+    Status,
+    /// Creating sessions and SNARKs
+    Create,
+    /// Requesting upload URLs and uploading images, inputs and receipts
+    Upload,
+    /// Downloading images and receipts
+    Download,
+    /// Every other API route
+    Other,
+}
+
+impl RouteGroup {
+    /// Classifies a request by its path below the base path.
+    ///
+    /// Returns `None` for routes that are never limited: the health check, the
+    /// metrics, the dashboard and the admin API.
+    fn of(method: &Method, path: &str) -> Option<Self> {
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match segments.as_slice() {
+            ["health"] | ["metrics"] | ["dashboard", ..] | ["admin", ..] => None,
+            ["sessions" | "snark", "status", _] | ["sessions", "logs", _] => Some(Self::Status),
+            [_, "create"] if method == Method::POST => Some(Self::Create),
+            [_, "upload", ..] => Some(Self::Upload),
+            _ if method == Method::PUT => Some(Self::Upload),
+            ["images" | "receipts", _] if method == Method::GET || method == Method::HEAD => {
+                Some(Self::Download)
+            }
+            _ => Some(Self::Other),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Create => "create",
+            Self::Upload => "upload",
+            Self::Download => "download",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// A token bucket: `burst` requests at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_second: f64,
+    /// Defaults to `per_second`, rounded up
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+impl RateLimit {
+    fn burst(&self) -> f64 {
+        self.burst
+            .map(f64::from)
+            .unwrap_or_else(|| self.per_second.ceil())
+            .max(1.0)
+    }
+}
+
+/// Rate limits per route group, as configured in the `[rate_limits]` section of the
+/// config file. Each API key, or client IP for requests without one, has its own
+/// bucket per group; groups without a limit are unlimited.
+///
+/// ```toml
+/// [rate_limits.groups.status]
+/// per_second = 2
+/// burst = 10
+///
+/// [rate_limits.keys.ci-key.status]
+/// per_second = 20
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub groups: HashMap<RouteGroup, RateLimit>,
+    /// Per API key limits, replacing those of `groups` they set
+    pub keys: HashMap<String, HashMap<RouteGroup, RateLimit>>,
+}
+
+impl RateLimits {
+    fn get(&self, group: RouteGroup, api_key: Option<&str>) -> Option<&RateLimit> {
+        api_key
+            .and_then(|key| self.keys.get(key))
+            .and_then(|limits| limits.get(&group))
+            .or_else(|| self.groups.get(&group))
+    }
+
+    /// Returns an error for every limit that refills slower than `MIN_PER_SECOND`.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let groups = self
+            .groups
+            .iter()
+            .map(|(group, limit)| (None, group, limit));
+        let keys = self.keys.iter().flat_map(|(key, limits)| {
+            limits
+                .iter()
+                .map(move |(group, limit)| (Some(key), group, limit))
+        });
+        for (key, group, limit) in groups.chain(keys) {
+            if !(limit.per_second >= MIN_PER_SECOND && limit.per_second.is_finite()) {
+                let key = key
+                    .map(|key| format!(" of key {key:?}"))
+                    .unwrap_or_default();
+                return Err(format!(
+                    "rate limit {group:?}{key}: per_second must be a number of at least \
+                     {MIN_PER_SECOND}, got {}",
+                    limit.per_second
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will be full again, after which it can be dropped
+    full_at: Instant,
+}
+
+/// Requests passed and rejected in one route group.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub(crate) struct GroupCounters {
+    allowed: u64,
+    rejected: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    buckets: HashMap<(RouteGroup, String), Bucket>,
+    counters: BTreeMap<RouteGroup, GroupCounters>,
+}
+
+pub(crate) struct RateLimiter {
+    limits: RateLimits,
+    /// API keys configured for rate limits or quotas, which get a bucket of their own
+    known_keys: HashSet<String>,
+    trusted_proxies: TrustedProxies,
+    inner: Mutex<Inner>,
+}
+
+pub(crate) type SharedRateLimiter = Arc<RateLimiter>;
+
+/// Limit and counters of one route group, reported by `GET /admin/state`.
+#[derive(Debug, Serialize)]
+pub(crate) struct GroupStats {
+    /// Default limit of the group, unlimited if `None`
+    limit: Option<RateLimit>,
+    #[serde(flatten)]
+    counters: GroupCounters,
+    /// Clients whose bucket is not full
+    active_clients: usize,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(
+        limits: RateLimits,
+        quota_keys: impl IntoIterator<Item = String>,
+        trusted_proxies: TrustedProxies,
+    ) -> Self {
+        let known_keys = limits.keys.keys().cloned().chain(quota_keys).collect();
+        Self {
+            limits,
+            known_keys,
+            trusted_proxies,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Takes a token from `client`'s bucket for `group`, or returns how long to wait
+    /// until one is available.
+    fn check(
+        &self,
+        group: RouteGroup,
+        api_key: Option<&str>,
+        client: &str,
+    ) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let result = match self.limits.get(group, api_key) {
+            Some(limit) => Self::take(&mut inner.buckets, (group, client.to_string()), limit),
+            None => Ok(()),
+        };
+        let counters = inner.counters.entry(group).or_default();
+        match result {
+            Ok(()) => counters.allowed += 1,
+            Err(_) => counters.rejected += 1,
+        }
+        result
+    }
+
+    fn take(
+        buckets: &mut HashMap<(RouteGroup, String), Bucket>,
+        key: (RouteGroup, String),
+        limit: &RateLimit,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = limit.burst();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+            // every bucket is in use: drop the one closest to full
+            if buckets.len() >= MAX_BUCKETS {
+                let closest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.full_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(closest) = closest {
+                    buckets.remove(&closest);
+                }
+            }
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+            full_at: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * limit.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(burst);
+        bucket.updated = now;
+        // time to refill `tokens`, capped so it neither overflows nor panics
+        let refill = |tokens: f64| {
+            Duration::try_from_secs_f64(tokens / limit.per_second)
+                .map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT))
+        };
+        if bucket.tokens < 1.0 {
+            return Err(refill(1.0 - bucket.tokens));
+        }
+        bucket.tokens -= 1.0;
+        bucket.full_at = now + refill(burst - bucket.tokens);
+        Ok(())
+    }
+
+    /// Drops the buckets that have refilled completely, returning how many are left.
+    pub(crate) fn prune(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        inner.buckets.retain(|_, bucket| bucket.full_at > now);
+        inner.buckets.len()
+    }
+
+    pub(crate) fn stats(&self) -> BTreeMap<RouteGroup, GroupStats> {
+        let inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        ROUTE_GROUPS
+            .into_iter()
+            .map(|group| {
+                let stats = GroupStats {
+                    limit: self.limits.groups.get(&group).copied(),
+                    counters: inner.counters.get(&group).copied().unwrap_or_default(),
+                    active_clients: inner
+                        .buckets
+                        .iter()
+                        .filter(|((g, _), bucket)| *g == group && bucket.full_at > now)
+                        .count(),
+                };
+                (group, stats)
+            })
+            .collect()
+    }
+
+    /// Renders the limits and counters of every route group in the Prometheus text
+    /// format.
+    fn metrics(&self) -> String {
+        let stats = self.stats();
+        let mut out = String::new();
+        // name, type, help and value of each metric family
+        type Family = (
+            &'static str,
+            &'static str,
+            &'static str,
+            fn(&GroupStats) -> Option<f64>,
+        );
+        let families: [Family; 5] = [
+            (
+                "bonsai_rate_limit_allowed_total",
+                "counter",
+                "Requests passed by the rate limiter",
+                |stats| Some(stats.counters.allowed as f64),
+            ),
+            (
+                "bonsai_rate_limit_rejected_total",
+                "counter",
+                "Requests rejected with 429 Too Many Requests",
+                |stats| Some(stats.counters.rejected as f64),
+            ),
+            (
+                "bonsai_rate_limit_active_clients",
+                "gauge",
+                "Clients whose bucket is not full",
+                |stats| Some(stats.active_clients as f64),
+            ),
+            (
+                "bonsai_rate_limit_per_second",
+                "gauge",
+                "Default refill rate of the route group, absent if unlimited",
+                |stats| stats.limit.map(|limit| limit.per_second),
+            ),
+            (
+                "bonsai_rate_limit_burst",
+                "gauge",
+                "Default burst of the route group, absent if unlimited",
+                |stats| stats.limit.map(|limit| limit.burst()),
+            ),
+        ];
+        for (name, kind, help, value) in families {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (group, stats) in &stats {
+                if let Some(value) = value(stats) {
+                    let _ = writeln!(out, "{name}{{group=\"{}\"}} {value}", group.as_str());
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `GET /metrics`: rate limiter metrics for Prometheus.
+pub(crate) async fn metrics(Extension(limiter): Extension<SharedRateLimiter>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        limiter.metrics(),
+    )
+}
+
+/// Middleware rejecting requests over the rate limit of their route group with
+/// `429 Too Many Requests` and a `Retry-After` header.
+pub(crate) async fn rate_limit(
+    State(limiter): State<SharedRateLimiter>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if let Some(group) = RouteGroup::of(request.method(), request.uri().path()) {
+        let headers = request.headers();
+        let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+        // only keys the server knows get a bucket of their own, so clients cannot
+        // dodge the limit of their address by sending a fresh key every time
+        let known_key = api_key.filter(|key| limiter.known_keys.contains(*key));
+        let client = match known_key {
+            Some(key) => format!("key:{key}"),
+            None => format!(
+                "ip:{}",
+                forwarded::client(headers, peer, &limiter.trusted_proxies)
+            ),
+        };
+        limiter
+            .check(group, api_key, &client)
+            .map_err(Error::RateLimited)?;
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_second: f64, burst: u32) -> RateLimiter {
+        let limits = RateLimits {
+            groups: HashMap::from([(
+                RouteGroup::Status,
+                RateLimit {
+                    per_second,
+                    burst: Some(burst),
+                },
+            )]),
+            keys: HashMap::from([(
+                "vip".to_string(),
+                HashMap::from([(
+                    RouteGroup::Status,
+                    RateLimit {
+                        per_second,
+                        burst: Some(burst * 2),
+                    },
+                )]),
+            )]),
+        };
+        RateLimiter::new(limits, [], TrustedProxies::none())
+    }
+
+    #[test]
+    fn test_route_groups() {
+        let group = |method: Method, path| RouteGroup::of(&method, path);
+        assert_eq!(group(Method::GET, "/health"), None);
+        assert_eq!(group(Method::GET, "/metrics"), None);
+        assert_eq!(group(Method::GET, "/dashboard/summary"), None);
+        assert_eq!(group(Method::POST, "/admin/cleanup"), None);
+        assert_eq!(
+            group(Method::GET, "/sessions/status/abc"),
+            Some(RouteGroup::Status)
+        );
+        assert_eq!(
+            group(Method::GET, "/snark/status/abc"),
+            Some(RouteGroup::Status)
+        );
+        assert_eq!(
+            group(Method::POST, "/sessions/create"),
+            Some(RouteGroup::Create)
+        );
+        assert_eq!(
+            group(Method::GET, "/images/upload/abc"),
+            Some(RouteGroup::Upload)
+        );
+        assert_eq!(group(Method::PUT, "/inputs/abc"), Some(RouteGroup::Upload));
+        assert_eq!(
+            group(Method::GET, "/receipts/abc"),
+            Some(RouteGroup::Download)
+        );
+        assert_eq!(
+            group(Method::DELETE, "/receipts/abc"),
+            Some(RouteGroup::Other)
+        );
+        assert_eq!(group(Method::GET, "/sessions"), Some(RouteGroup::Other));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(1.0, 2);
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+        let retry_after = limiter.check(RouteGroup::Status, None, "ip:a").unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+
+        // other clients and unlimited groups are unaffected
+        assert!(limiter.check(RouteGroup::Status, None, "ip:b").is_ok());
+        for _ in 0..10 {
+            assert!(limiter.check(RouteGroup::Other, None, "ip:a").is_ok());
+        }
+
+        // per-key limits replace the group's
+        for _ in 0..4 {
+            assert!(limiter
+                .check(RouteGroup::Status, Some("vip"), "key:vip")
+                .is_ok());
+        }
+        assert!(limiter
+            .check(RouteGroup::Status, Some("vip"), "key:vip")
+            .is_err());
+
+        let stats = limiter.stats();
+        assert_eq!(stats[&RouteGroup::Status].counters.allowed, 7);
+        assert_eq!(stats[&RouteGroup::Status].counters.rejected, 2);
+        assert_eq!(stats[&RouteGroup::Status].active_clients, 3);
+        assert_eq!(stats[&RouteGroup::Other].counters.allowed, 10);
+    }
+
+    #[test]
+    fn test_refill_and_prune() {
+        let limiter = limiter(1000.0, 1);
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+        assert_eq!(limiter.prune(), 1);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(limiter.prune(), 0);
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+    }
+
+    #[test]
+    fn test_slow_refill_does_not_overflow() {
+        let limiter = limiter(1e-300, 1);
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+        let retry_after = limiter.check(RouteGroup::Status, None, "ip:a").unwrap_err();
+        assert_eq!(retry_after, MAX_WAIT);
+    }
+
+    #[test]
+    fn test_full_buckets_are_evicted() {
+        let limiter = limiter(1000.0, 1);
+        for client in 0..MAX_BUCKETS {
+            assert!(limiter
+                .check(RouteGroup::Status, None, &format!("ip:{client}"))
+                .is_ok());
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limiter.check(RouteGroup::Status, None, "ip:new").is_ok());
+        let inner = limiter.inner.lock().unwrap();
+        assert_eq!(inner.buckets.len(), 1);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let limiter = limiter(1.0, 1);
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_ok());
+        assert!(limiter.check(RouteGroup::Status, None, "ip:a").is_err());
+        let metrics = limiter.metrics();
+        for line in [
+            "# TYPE bonsai_rate_limit_allowed_total counter",
+            "bonsai_rate_limit_allowed_total{group=\"status\"} 1",
+            "bonsai_rate_limit_rejected_total{group=\"status\"} 1",
+            "bonsai_rate_limit_active_clients{group=\"status\"} 1",
+            "bonsai_rate_limit_per_second{group=\"status\"} 1",
+            "bonsai_rate_limit_burst{group=\"status\"} 1",
+            "bonsai_rate_limit_allowed_total{group=\"other\"} 0",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{line} in {metrics}");
+        }
+        // unlimited groups have no limit
+        assert!(!metrics.contains("bonsai_rate_limit_per_second{group=\"other\"}"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let limits: RateLimits = toml::from_str(
+            r#"
+            [groups.status]
+            per_second = 0.5
+
+            [keys.ci.create]
+            per_second = 0
+            "#,
+        )
+        .unwrap();
+        assert!(limits.validate().is_err());
+        let limits: RateLimits = toml::from_str("[groups.status]\nper_second = 1e-300").unwrap();
+        assert!(limits.validate().is_err());
+        assert!(toml::from_str::<RateLimits>("[groups.polling]\nper_second = 1").is_err());
+    }
+}
@@ -13,39 +13,90 @@
 // limitations under the License.
 
 use axum::{
-    body::Bytes,
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    body::Body,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        ConnectInfo, Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use bonsai_sdk::responses::{
-    CreateSessRes, ImgUploadRes, ProofReq, SessionStats, SessionStatusRes, SnarkReq,
-    SnarkStatusRes, UploadRes,
+    CreateSessRes, ImgUploadRes, ProofReq, Quotas, SessionStats, SessionStatusRes, SnarkReq,
+    SnarkStatusRes, UploadRes, VersionInfo,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::time::Duration;
-use tracing::info;
+use tracing::{info, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::{
+    blob::{blob_response, Blob, BlobOptions},
     error::Error,
-    prover::{ProverHandle, Task},
-    state::{AppState, SessionStatus},
+    listener::Peer,
+    prover::{ExecutorLimits, ProverHandle, Task},
+    quota::{Quota, SharedQuotas},
+    state::{AppState, BonsaiState, PendingSession, Resource, SessionInfo, SessionStatus},
     url_resolver::SharedUrlResolver,
+    version::{SupportedVersions, VERSION_HEADER},
 };
 
+/// Header the Bonsai SDK sends the API key in, used to tell tenants apart.
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+/// Number of sessions returned by `GET /sessions` when no `limit` is given.
+const DEFAULT_SESSION_PAGE_SIZE: usize = 100;
+/// Upper bound on the `limit` accepted by `GET /sessions`.
+const MAX_SESSION_PAGE_SIZE: usize = 1000;
+
+/// Returns the API key identifying the tenant of a request, if any.
+fn tenant(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Rejects an upload of `size` bytes to `id` that would take the tenant over its
+/// `stored_bytes` quota. An entry replaced by the upload no longer counts.
+fn check_storage_quota(
+    state: &BonsaiState,
+    quota: &Quota,
+    tenant: Option<&str>,
+    (resource, id): (Resource, &str),
+    size: u64,
+) -> Result<(), Error> {
+    let Some(limit) = quota.stored_bytes else {
+        return Ok(());
+    };
+    let stored = state.stored_bytes(tenant, Some((resource, id)));
+    if stored.saturating_add(size) > limit {
+        return Err(Error::QuotaExceeded(
+            format!(
+                "storing {size} more bytes would exceed the limit of {limit} bytes, {stored} bytes are in use"
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the upload URL for an image, or `204 No Content` if the image already
+/// exists, which is how hosted Bonsai (and the SDK's `upload_img`) signal it.
 pub(crate) async fn get_image_upload(
     State(s): State<AppState>,
     Path(image_id): Path<String>,
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
-) -> Result<Json<ImgUploadRes>, Error> {
+) -> Result<Response, Error> {
     let state = &s.read()?;
     match state.get_image(&image_id) {
-        Some(_) => Err(Error::ImageIdExists),
+        Some(_) => Ok(StatusCode::NO_CONTENT.into_response()),
         None => {
             let base_url = url_resolver
-                .resolve(&headers)
+                .resolve(&headers, peer)
                 .map_err(|_| Error::ServerUrlResolution)?;
             info!("base_url: {}", base_url);
             Ok(Json(ImgUploadRes {
@@ -54,7 +105,8 @@ pub(crate) async fn get_image_upload(
                     base_url.as_str().trim_end_matches('/'),
                     image_id
                 ),
-            }))
+            })
+            .into_response())
         }
     }
 }
@@ -62,21 +114,117 @@ pub(crate) async fn get_image_upload(
 pub(crate) async fn put_image_upload(
     State(s): State<AppState>,
     Path(image_id): Path<String>,
-    body: Bytes,
+    Extension(blob_options): Extension<BlobOptions>,
+    Extension(quotas): Extension<SharedQuotas>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<(), Error> {
-    s.write()?.put_image(image_id.clone(), body.to_vec());
+    let tenant = tenant(&headers);
+    let quota = quotas.for_tenant(tenant.as_deref());
+    let image = Blob::from_body(body, &blob_options).await?;
+    let mut state = s.write()?;
+    check_storage_quota(
+        &state,
+        &quota,
+        tenant.as_deref(),
+        (Resource::Image, &image_id),
+        image.len(),
+    )?;
+    state.put_image(image_id.clone(), image, tenant);
     info!("ImageID {image_id} uploaded");
     Ok(())
 }
 
+/// Summary of a stored image returned by `GET /images`.
+#[derive(Serialize)]
+pub(crate) struct ImageInfo {
+    image_id: String,
+    /// Size of the ELF in bytes
+    size: u64,
+    /// Upload time in seconds since the Unix epoch
+    uploaded_at: u64,
+    /// Time at which the image expires, in seconds since the Unix epoch
+    expires_at: u64,
+}
+
+pub(crate) async fn list_images(State(s): State<AppState>) -> Result<Json<Vec<ImageInfo>>, Error> {
+    let state = s.read()?;
+    let ttl = state.ttl.as_secs();
+    let mut images: Vec<ImageInfo> = state
+        .list_images()
+        .map(|(image_id, entry)| ImageInfo {
+            image_id: image_id.clone(),
+            size: entry.data.len(),
+            uploaded_at: entry.created_at_unix(),
+            expires_at: entry.created_at_unix() + ttl,
+        })
+        .collect();
+    images.sort_by(|a, b| (a.uploaded_at, &a.image_id).cmp(&(b.uploaded_at, &b.image_id)));
+    Ok(Json(images))
+}
+
+pub(crate) async fn get_image(
+    State(s): State<AppState>,
+    Path(image_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let image = {
+        let state = s.read()?;
+        state
+            .get_image(&image_id)
+            .ok_or_else(|| state.missing(Resource::Image, &image_id))?
+    };
+    blob_response(image, &headers).await
+}
+
+pub(crate) async fn head_image(
+    State(s): State<AppState>,
+    Path(image_id): Path<String>,
+) -> Result<Response, Error> {
+    let state = s.read()?;
+    let image = state
+        .get_image(&image_id)
+        .ok_or_else(|| state.missing(Resource::Image, &image_id))?;
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_LENGTH, image.len().to_string()),
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+        ],
+    )
+        .into_response())
+}
+
+pub(crate) async fn delete_image(
+    State(s): State<AppState>,
+    Path(image_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    let mut state = s.write()?;
+    if state.owned_by_other(Resource::Image, &image_id, tenant(&headers).as_deref()) {
+        return Err(state.missing(Resource::Image, &image_id));
+    }
+    if state.in_use(Resource::Image, &image_id) {
+        return Err(Error::InUse(Resource::Image, image_id));
+    }
+    match state.remove_image(&image_id) {
+        Some(_) => {
+            info!("ImageID {image_id} deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(state.missing(Resource::Image, &image_id)),
+    }
+}
+
 pub(crate) async fn get_input_upload(
     State(_): State<AppState>,
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
 ) -> Result<Json<UploadRes>, Error> {
     let input_id = uuid::Uuid::new_v4();
     let base_url = url_resolver
-        .resolve(&headers)
+        .resolve(&headers, peer)
         .map_err(|_| Error::ServerUrlResolution)?;
     info!("base_url: {}", base_url);
     Ok(Json(UploadRes {
@@ -92,30 +240,153 @@ pub(crate) async fn get_input_upload(
 pub(crate) async fn put_input_upload(
     State(s): State<AppState>,
     Path(input_id): Path<String>,
-    body: Bytes,
+    Extension(blob_options): Extension<BlobOptions>,
+    Extension(quotas): Extension<SharedQuotas>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<(), Error> {
-    s.write()?.put_input(input_id, body.to_vec());
+    let tenant = tenant(&headers);
+    let quota = quotas.for_tenant(tenant.as_deref());
+    let input = Blob::from_body(body, &blob_options).await?;
+    let mut state = s.write()?;
+    check_storage_quota(
+        &state,
+        &quota,
+        tenant.as_deref(),
+        (Resource::Input, &input_id),
+        input.len(),
+    )?;
+    state.put_input(input_id, input, tenant);
     Ok(())
 }
 
+/// A Bonsai [`ProofReq`] extended with optional bonsai-local executor overrides.
+#[derive(Deserialize)]
+pub(crate) struct CreateSessionReq {
+    #[serde(flatten)]
+    proof: ProofReq,
+    /// Segment size as a power of two, overriding the server default
+    #[serde(default)]
+    segment_limit_po2: Option<u32>,
+    /// Wall-clock limit in seconds, capped by the server-wide timeout
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+pub(crate) async fn delete_input(
+    State(s): State<AppState>,
+    Path(input_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    let mut state = s.write()?;
+    if state.owned_by_other(Resource::Input, &input_id, tenant(&headers).as_deref()) {
+        return Err(state.missing(Resource::Input, &input_id));
+    }
+    if state.in_use(Resource::Input, &input_id) {
+        return Err(Error::InUse(Resource::Input, input_id));
+    }
+    match state.remove_input(&input_id) {
+        Some(_) => {
+            info!("InputID {input_id} deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(state.missing(Resource::Input, &input_id)),
+    }
+}
+
 pub(crate) async fn create_session(
     Extension(prover_handle): Extension<ProverHandle>,
+    Extension(default_limits): Extension<ExecutorLimits>,
+    Extension(quotas): Extension<SharedQuotas>,
+    Extension(versions): Extension<SupportedVersions>,
     State(s): State<AppState>,
-    Json(request): Json<ProofReq>,
+    headers: HeaderMap,
+    request: Result<Json<CreateSessionReq>, JsonRejection>,
 ) -> Result<Json<CreateSessRes>, Error> {
+    let Json(request) = request?;
+    if prover_handle.control.is_shutting_down() {
+        return Err(Error::ShuttingDown);
+    }
+    let tenant = tenant(&headers);
+    let quota = quotas.for_tenant(tenant.as_deref());
+    // `exec_cycle_limit` is expressed in millions of cycles, as in hosted Bonsai
+    let mut limits = default_limits
+        .with_overrides(
+            request.segment_limit_po2,
+            request
+                .proof
+                .exec_cycle_limit
+                .map(|mcycles| mcycles.saturating_mul(1_000_000)),
+            request.timeout_secs.map(Duration::from_secs),
+        )?
+        .with_overrides(None, quota.max_cycles, None)?;
+    let request = request.proof;
     let session_id = uuid::Uuid::new_v4();
     info!("create_session: {}", session_id);
-    s.write()?
-        .put_session(session_id.to_string(), SessionStatus::Running, None);
+    {
+        let mut state = s.write()?;
+        if let Some(limit) = quota.concurrent_sessions {
+            if state.running_sessions(tenant.as_deref()) >= limit {
+                return Err(Error::QuotaExceeded(
+                    format!("limit of {limit} concurrent sessions reached"),
+                    None,
+                ));
+            }
+        }
+        let mut info = SessionInfo::new(request.img.clone(), tenant.clone());
+        if let Some(budget) = quota.cycles_per_day {
+            let used = state.cycles.last_day(tenant.as_deref());
+            let reserved = state.reserved_cycles(tenant.as_deref());
+            let remaining = budget.saturating_sub(used.saturating_add(reserved));
+            if remaining == 0 {
+                // the budget frees up as the oldest cycles leave the 24 hour window, or
+                // as running sessions finish
+                let retry_after = if used >= budget {
+                    state.cycles.next_expiry(tenant.as_deref())
+                } else {
+                    None
+                };
+                return Err(Error::QuotaExceeded(
+                    format!("daily budget of {budget} cycles used up or held by running sessions"),
+                    retry_after,
+                ));
+            }
+            // the session may use at most what is left of the budget, which it holds until
+            // it finishes, so concurrent sessions cannot overspend it together
+            limits = limits.with_overrides(None, Some(remaining), None)?;
+            info.reserved_cycles = limits.max_cycles.unwrap_or(remaining);
+        }
+        state.put_session_info(session_id.to_string(), info);
+        // keep the image, input and assumptions from being deleted until the prover has
+        // read them
+        state.reserve(
+            session_id.to_string(),
+            PendingSession {
+                image_id: request.img.clone(),
+                input_id: request.input.clone(),
+                assumptions: request.assumptions.clone(),
+            },
+        );
+    }
     let task = Task {
         image_id: request.img,
         input_id: request.input,
         session_id: session_id.to_string(),
         assumptions: request.assumptions,
+        limits,
+        execute_only: request.execute_only,
+        // clients of other zkVM versions are proven by a matching r0vm
+        r0vm: versions.r0vm_for(headers.get(VERSION_HEADER).and_then(|v| v.to_str().ok())),
+        // links the prover spans to the request span and thus to the client's trace
+        trace_context: Span::current().context(),
     };
-    prover_handle
-        .execute(task, Duration::from_secs(120))
-        .await?;
+    if let Err(err) = prover_handle.execute(task, Duration::from_secs(120)).await {
+        // a session that never reached the queue must not count as running
+        let mut state = s.write()?;
+        state.release(session_id.to_string());
+        state.remove_session(session_id.to_string());
+        return Err(err);
+    }
 
     Ok(Json(CreateSessRes {
         uuid: session_id.to_string(),
@@ -126,22 +397,24 @@ pub(crate) async fn session_status(
     State(s): State<AppState>,
     Path(session_id): Path<String>,
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
 ) -> Result<Json<SessionStatusRes>, Error> {
     let storage = s.read()?;
-    let (status, stats) = storage
+    let SessionInfo {
+        status,
+        stats,
+        error_msg,
+        ..
+    } = storage
         .get_session(&session_id)
-        .ok_or_else(|| anyhow::anyhow!("Session not found for session id: {:?}", &session_id))?;
+        .ok_or_else(|| storage.missing(Resource::Session, &session_id))?;
     let receipt = storage.get_receipt(&session_id);
-    let stats = stats.as_ref().map(|stats| SessionStats {
-        segments: stats.segments,
-        total_cycles: stats.total_cycles,
-        cycles: stats.user_cycles,
-    });
+    let stats = stats.as_ref().map(session_stats);
     match receipt {
         Some(_) => {
             let base_url = url_resolver
-                .resolve(&headers)
+                .resolve(&headers, peer)
                 .map_err(|_| Error::ServerUrlResolution)?;
             info!("base_url: {}", base_url);
             Ok(Json(SessionStatusRes {
@@ -160,17 +433,175 @@ pub(crate) async fn session_status(
         None => Ok(Json(SessionStatusRes {
             status: status.to_string(),
             receipt_url: None,
-            error_msg: None,
+            error_msg: error_msg.clone(),
             state: None,
             elapsed_time: None,
-            stats: None,
+            // execute-only sessions succeed with stats but without a receipt
+            stats,
         })),
     }
 }
 
+fn session_stats(stats: &risc0_zkvm::SessionStats) -> SessionStats {
+    SessionStats {
+        segments: stats.segments,
+        total_cycles: stats.total_cycles,
+        cycles: stats.user_cycles,
+    }
+}
+
+/// Filters and pagination accepted by `GET /sessions`.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct SessionQuery {
+    status: Option<SessionStatus>,
+    image_id: Option<String>,
+    /// Only sessions created at or after this time, in seconds since the Unix epoch
+    created_after: Option<u64>,
+    /// Only sessions created before this time, in seconds since the Unix epoch
+    created_before: Option<u64>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl SessionQuery {
+    fn matches(&self, info: &SessionInfo) -> bool {
+        let created_at = info.created_at_unix();
+        self.status.is_none_or(|status| info.status == status)
+            && self
+                .image_id
+                .as_ref()
+                .is_none_or(|image_id| &info.image_id == image_id)
+            && self.created_after.is_none_or(|after| created_at >= after)
+            && self.created_before.is_none_or(|before| created_at < before)
+    }
+}
+
+/// Summary of a session returned by `GET /sessions`.
+#[derive(Serialize)]
+pub(crate) struct SessionSummary {
+    uuid: String,
+    status: String,
+    image_id: String,
+    /// Creation time in seconds since the Unix epoch
+    created_at: u64,
+    /// Proving time in milliseconds, once the session has finished
+    duration_ms: Option<u64>,
+    stats: Option<SessionStats>,
+}
+
+/// A page of the caller's sessions, newest first.
+#[derive(Serialize)]
+pub(crate) struct SessionList {
+    sessions: Vec<SessionSummary>,
+    /// Number of sessions matching the filters, across all pages
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+/// Lists the sessions created with the caller's `x-api-key`, or without one if the
+/// request carries none.
+pub(crate) async fn list_sessions(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    query: Result<Query<SessionQuery>, QueryRejection>,
+) -> Result<Json<SessionList>, Error> {
+    let Query(query) = query.map_err(|rejection| Error::InvalidRequest(rejection.body_text()))?;
+    let tenant = tenant(&headers);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SESSION_PAGE_SIZE)
+        .min(MAX_SESSION_PAGE_SIZE);
+
+    let state = s.read()?;
+    let mut sessions: Vec<_> = state
+        .list_sessions()
+        .filter(|(_, info)| info.tenant == tenant && query.matches(info))
+        .collect();
+    sessions.sort_by(|(a_id, a), (b_id, b)| (b.created_at, b_id).cmp(&(a.created_at, a_id)));
+    let total = sessions.len();
+    let sessions = sessions
+        .into_iter()
+        .skip(query.offset)
+        .take(limit)
+        .map(|(session_id, info)| SessionSummary {
+            uuid: session_id.clone(),
+            status: info.status.to_string(),
+            image_id: info.image_id.clone(),
+            created_at: info.created_at_unix(),
+            duration_ms: info.duration.map(|d| d.as_millis() as u64),
+            stats: info.stats.as_ref().map(session_stats),
+        })
+        .collect();
+
+    Ok(Json(SessionList {
+        sessions,
+        total,
+        offset: query.offset,
+        limit,
+    }))
+}
+
+/// Deletes a finished session along with its receipt and logs.
+pub(crate) async fn delete_session(
+    State(s): State<AppState>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    let mut state = s.write()?;
+    if state.owned_by_other(Resource::Session, &session_id, tenant(&headers).as_deref()) {
+        return Err(state.missing(Resource::Session, &session_id));
+    }
+    match state.get_session(&session_id) {
+        Some(info) if info.status == SessionStatus::Running => {
+            Err(Error::SessionRunning(session_id))
+        }
+        Some(_) => {
+            state.remove_session(&session_id);
+            info!("SessionID {session_id} deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(state.missing(Resource::Session, &session_id)),
+    }
+}
+
+pub(crate) async fn session_logs(
+    State(s): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<String, Error> {
+    let storage = s.read()?;
+    storage
+        .get_session(&session_id)
+        .ok_or_else(|| storage.missing(Resource::Session, &session_id))?;
+    // sessions still waiting in the queue have not produced any output yet
+    match storage.get_session_log(&session_id) {
+        Some(log) => Ok(log.lock()?.contents()),
+        None => Ok(String::new()),
+    }
+}
+
+/// Aborts a queued or running session, like `SessionId::stop` in the Bonsai SDK.
+///
+/// Stopping a session that has already finished has no effect.
+pub(crate) async fn stop_session(
+    State(s): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, Error> {
+    let mut state = s.write()?;
+    if state.get_session(&session_id).is_none() {
+        return Err(state.missing(Resource::Session, &session_id));
+    }
+    if state.abort_session(&session_id) {
+        info!("SessionID {session_id} aborted");
+    }
+    Ok(StatusCode::OK)
+}
+
 pub(crate) async fn create_snark(
-    Json(request): Json<SnarkReq>,
+    request: Result<Json<SnarkReq>, JsonRejection>,
 ) -> Result<Json<CreateSessRes>, Error> {
+    let Json(request) = request?;
     info!("create_snark: {}", request.session_id);
     Ok(Json(CreateSessRes {
         uuid: request.session_id,
@@ -181,17 +612,20 @@ pub(crate) async fn snark_status(
     State(s): State<AppState>,
     Path(snark_id): Path<String>,
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
 ) -> Result<Json<SnarkStatusRes>, Error> {
     let storage = s.read()?;
-    storage
+    let SessionInfo {
+        status, error_msg, ..
+    } = storage
         .get_session(&snark_id)
-        .ok_or_else(|| anyhow::anyhow!("Snark status not found for snark id: {:?}", &snark_id))?;
+        .ok_or_else(|| storage.missing(Resource::Session, &snark_id))?;
     let receipt = storage.get_receipt(&snark_id);
     match receipt {
         Some(_) => {
             let base_url = url_resolver
-                .resolve(&headers)
+                .resolve(&headers, peer)
                 .map_err(|_| Error::ServerUrlResolution)?;
             info!("base_url: {}", base_url);
             Ok(Json(SnarkStatusRes {
@@ -204,10 +638,11 @@ pub(crate) async fn snark_status(
                 error_msg: None,
             }))
         }
+        // report failed sessions as such instead of leaving clients polling forever
         None => Ok(Json(SnarkStatusRes {
-            status: SessionStatus::Running.to_string(),
+            status: status.to_string(),
             output: None,
-            error_msg: None,
+            error_msg: error_msg.clone(),
         })),
     }
 }
@@ -215,24 +650,49 @@ pub(crate) async fn snark_status(
 pub(crate) async fn get_receipt(
     State(s): State<AppState>,
     Path(session_id): Path<String>,
-) -> Result<Vec<u8>, Error> {
+    headers: HeaderMap,
+) -> Result<Response, Error> {
     info!("get_receipt: {}", session_id);
-    let storage = s.read()?;
-    let receipt = storage
-        .get_receipt(&session_id)
-        .ok_or_else(|| anyhow::anyhow!("Receipt not found for session id: {:?}", &session_id))?;
-    Ok(receipt)
+    let receipt = {
+        let storage = s.read()?;
+        storage
+            .get_receipt(&session_id)
+            .ok_or_else(|| storage.missing(Resource::Receipt, &session_id))?
+    };
+    blob_response(receipt, &headers).await
+}
+
+pub(crate) async fn delete_receipt(
+    State(s): State<AppState>,
+    Path(receipt_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    let mut state = s.write()?;
+    if state.owned_by_other(Resource::Receipt, &receipt_id, tenant(&headers).as_deref()) {
+        return Err(state.missing(Resource::Receipt, &receipt_id));
+    }
+    if state.in_use(Resource::Receipt, &receipt_id) {
+        return Err(Error::InUse(Resource::Receipt, receipt_id));
+    }
+    match state.remove_receipt(&receipt_id) {
+        Some(_) => {
+            info!("ReceiptID {receipt_id} deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(state.missing(Resource::Receipt, &receipt_id)),
+    }
 }
 
 pub(crate) async fn get_receipt_upload(
     State(s): State<AppState>,
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
 ) -> Result<Json<UploadRes>, Error> {
     let _state = &s.read()?;
     let receipt_id = uuid::Uuid::new_v4();
     let base_url = url_resolver
-        .resolve(&headers)
+        .resolve(&headers, peer)
         .map_err(|_| Error::ServerUrlResolution)?;
     info!("base_url: {}", base_url);
     Ok(Json(UploadRes {
@@ -248,12 +708,96 @@ pub(crate) async fn get_receipt_upload(
 pub(crate) async fn put_receipt(
     State(s): State<AppState>,
     Path(receipt_id): Path<String>,
-    body: Bytes,
+    Extension(blob_options): Extension<BlobOptions>,
+    Extension(quotas): Extension<SharedQuotas>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<(), Error> {
-    s.write()?.put_receipt(receipt_id.clone(), body.to_vec());
+    let tenant = tenant(&headers);
+    let quota = quotas.for_tenant(tenant.as_deref());
+    let receipt = Blob::from_body(body, &blob_options).await?;
+    let mut state = s.write()?;
+    check_storage_quota(
+        &state,
+        &quota,
+        tenant.as_deref(),
+        (Resource::Receipt, &receipt_id),
+        receipt.len(),
+    )?;
+    state.put_receipt(receipt_id.clone(), receipt, tenant);
     Ok(())
 }
 
+/// Hosted Bonsai's [`Quotas`] plus the usage behind them and the limits it has no
+/// field for. Unlimited values are reported as `i64::MAX` in the Bonsai fields and
+/// omitted from the others.
+#[derive(Serialize)]
+pub(crate) struct QuotasRes {
+    #[serde(flatten)]
+    quotas: Quotas,
+    /// Queued or running sessions
+    running_sessions: usize,
+    /// Executor cycles used within the last 24 hours
+    cycles_last_day: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cycles_per_day: Option<u64>,
+    /// Size of the stored images, inputs and receipts in bytes
+    stored_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stored_bytes_limit: Option<u64>,
+}
+
+/// Reports the quotas and usage of the tenant identified by the `x-api-key` header.
+pub(crate) async fn user_quotas(
+    State(s): State<AppState>,
+    Extension(default_limits): Extension<ExecutorLimits>,
+    Extension(quotas): Extension<SharedQuotas>,
+    headers: HeaderMap,
+) -> Result<Json<QuotasRes>, Error> {
+    fn or_max(value: Option<u64>) -> i64 {
+        value.map_or(i64::MAX, |value| i64::try_from(value).unwrap_or(i64::MAX))
+    }
+
+    let tenant = tenant(&headers);
+    let tenant = tenant.as_deref();
+    let quota = quotas.for_tenant(tenant);
+    let max_cycles = default_limits
+        .with_overrides(None, quota.max_cycles, None)?
+        .max_cycles;
+    let state = s.read()?;
+    let cycles_last_day = state.cycles.last_day(tenant);
+    let cycles_held = cycles_last_day.saturating_add(state.reserved_cycles(tenant));
+    Ok(Json(QuotasRes {
+        quotas: Quotas {
+            // in millions of cycles, like `exec_cycle_limit` of a proof request
+            exec_cycle_limit: or_max(max_cycles.map(|cycles| cycles / 1_000_000)),
+            concurrent_proofs: or_max(quota.concurrent_sessions.map(|n| n as u64)),
+            cycle_budget: or_max(
+                quota
+                    .cycles_per_day
+                    .map(|budget| budget.saturating_sub(cycles_held)),
+            ),
+            cycle_usage: or_max(Some(state.cycles.lifetime(tenant))),
+            dedicated_executor: 0,
+            dedicated_gpu: 0,
+        },
+        running_sessions: state.running_sessions(tenant),
+        cycles_last_day,
+        cycles_per_day: quota.cycles_per_day,
+        stored_bytes: state.stored_bytes(tenant, None),
+        stored_bytes_limit: quota.stored_bytes,
+    }))
+}
+
+/// Lists the risc0-zkvm versions the server proves with, like hosted Bonsai.
+pub(crate) async fn version(
+    Extension(versions): Extension<SupportedVersions>,
+) -> Json<VersionInfo> {
+    Json(VersionInfo {
+        risc0_zkvm: versions.list(),
+    })
+}
+
 pub(crate) async fn health_check() -> impl IntoResponse {
     (
         StatusCode::OK,
@@ -266,19 +810,273 @@ pub(crate) async fn health_check() -> impl IntoResponse {
 
 pub(crate) async fn resolved_server_url(
     Extension(url_resolver): Extension<SharedUrlResolver>,
+    ConnectInfo(peer): ConnectInfo<Peer>,
     headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>, Error> {
     let resolved_url = url_resolver
-        .resolve(&headers)
+        .resolve(&headers, peer)
         .map_err(|_| Error::ServerUrlResolution)?;
 
     Ok(Json(json!({
         "resolved_server_url": resolved_url.to_string(),
+        // forwarded headers are ignored unless the peer is a trusted proxy
+        "trusted_proxy": url_resolver.trusts(peer),
         "headers": {
             "forwarded": headers.get("forwarded").and_then(|v| v.to_str().ok()),
             "x-forwarded-proto": headers.get("x-forwarded-proto").and_then(|v| v.to_str().ok()),
             "x-forwarded-host": headers.get("x-forwarded-host").and_then(|v| v.to_str().ok()),
             "x-forwarded-port": headers.get("x-forwarded-port").and_then(|v| v.to_str().ok()),
+            "x-forwarded-prefix": headers.get("x-forwarded-prefix").and_then(|v| v.to_str().ok()),
         }
     })))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prover::{ProverControl, ProverSettings},
+        quota::Quotas,
+    };
+    use std::sync::{Arc, RwLock};
+
+    #[tokio::test]
+    async fn test_create_session_quotas() {
+        let state: AppState = Arc::new(RwLock::new(BonsaiState::new(Duration::from_secs(60))));
+        let (sender, _receiver) = tokio::sync::mpsc::channel(8);
+        let prover_handle = ProverHandle {
+            sender,
+            control: ProverControl::new(ProverSettings {
+                paused: false,
+                workers: 1,
+            }),
+        };
+        let limits = ExecutorLimits {
+            segment_limit_po2: 20,
+            max_cycles: None,
+            timeout: None,
+        };
+        let mut quotas = Quotas::default();
+        quotas.keys.insert(
+            "key".to_string(),
+            Quota {
+                concurrent_sessions: Some(1),
+                cycles_per_day: Some(1000),
+                ..Default::default()
+            },
+        );
+        quotas.keys.insert(
+            "parallel".to_string(),
+            Quota {
+                cycles_per_day: Some(1000),
+                ..Default::default()
+            },
+        );
+        let quotas = Arc::new(quotas);
+        let create_with = |key: &str, prover_handle: ProverHandle| {
+            let mut headers = HeaderMap::new();
+            headers.insert(API_KEY_HEADER, key.parse().unwrap());
+            let request = serde_json::from_value(json!({
+                "img": "image",
+                "input": "input",
+                "assumptions": [],
+                "execute_only": false,
+            }))
+            .unwrap();
+            create_session(
+                Extension(prover_handle),
+                Extension(limits.clone()),
+                Extension(Arc::clone(&quotas)),
+                Extension(SupportedVersions::new(vec![])),
+                State(Arc::clone(&state)),
+                headers,
+                Ok(Json(request)),
+            )
+        };
+        let create = || create_with("key", prover_handle.clone());
+
+        let Json(session) = create().await.unwrap();
+        let Err(err) = create().await else {
+            panic!("second concurrent session was accepted");
+        };
+        assert!(
+            matches!(&err, Error::QuotaExceeded(msg, None) if msg.contains("concurrent")),
+            "{err:?}"
+        );
+
+        // the finished session used up the daily budget
+        state.write().unwrap().put_session(
+            session.uuid,
+            SessionStatus::Succeeded,
+            Some(crate::backend::session_stats(1, 1000, 900).unwrap()),
+        );
+        let Err(err) = create().await else {
+            panic!("session over the daily budget was accepted");
+        };
+        let Error::QuotaExceeded(ref msg, Some(retry_after)) = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(msg.contains("daily budget"));
+        assert!(retry_after > Duration::from_secs(23 * 60 * 60));
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        // a running session holds what is left of the budget, so a concurrent one
+        // cannot spend it again
+        assert!(create_with("parallel", prover_handle.clone()).await.is_ok());
+        let Err(err) = create_with("parallel", prover_handle.clone()).await else {
+            panic!("session over the reserved daily budget was accepted");
+        };
+        assert!(
+            matches!(&err, Error::QuotaExceeded(msg, None) if msg.contains("running sessions")),
+            "{err:?}"
+        );
+
+        // a session that cannot be queued is not left running
+        let (sender, receiver) = tokio::sync::mpsc::channel(8);
+        drop(receiver);
+        let closed = ProverHandle {
+            sender,
+            control: prover_handle.control.clone(),
+        };
+        assert!(create_with("other", closed).await.is_err());
+        assert_eq!(state.read().unwrap().running_sessions(Some("other")), 0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_checks_tenant() {
+        let state: AppState = Arc::new(RwLock::new(BonsaiState::new(Duration::from_secs(60))));
+        {
+            let mut state = state.write().unwrap();
+            let owner = || Some("key".to_string());
+            state.put_image("image".to_string(), vec![1].into(), owner());
+            state.put_input("input".to_string(), vec![2].into(), owner());
+            state.put_receipt("receipt".to_string(), vec![3].into(), owner());
+            let mut info = SessionInfo::new("image".to_string(), owner());
+            info.status = SessionStatus::Succeeded;
+            state.put_session_info("session".to_string(), info);
+        }
+        let headers = |key: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(API_KEY_HEADER, key.parse().unwrap());
+            headers
+        };
+        let id = |id: &str| Path(id.to_string());
+
+        for key in [headers("other"), HeaderMap::new()] {
+            let s = || State(Arc::clone(&state));
+            let err = delete_image(s(), id("image"), key.clone()).await;
+            assert!(matches!(err, Err(Error::ImageNotFound(_))), "{err:?}");
+            let err = delete_input(s(), id("input"), key.clone()).await;
+            assert!(matches!(err, Err(Error::InputNotFound(_))), "{err:?}");
+            let err = delete_receipt(s(), id("receipt"), key.clone()).await;
+            assert!(matches!(err, Err(Error::ReceiptNotFound(_))), "{err:?}");
+            let err = delete_session(s(), id("session"), key).await;
+            assert!(matches!(err, Err(Error::SessionNotFound(_))), "{err:?}");
+        }
+        {
+            let state = state.read().unwrap();
+            assert!(state.get_image("image").is_some());
+            assert!(state.get_input("input").is_some());
+            assert!(state.get_receipt("receipt").is_some());
+            assert!(state.get_session("session").is_some());
+        }
+
+        let s = || State(Arc::clone(&state));
+        let owner = || headers("key");
+        assert!(matches!(
+            delete_image(s(), id("image"), owner()).await,
+            Ok(StatusCode::NO_CONTENT)
+        ));
+        assert!(matches!(
+            delete_input(s(), id("input"), owner()).await,
+            Ok(StatusCode::NO_CONTENT)
+        ));
+        assert!(matches!(
+            delete_receipt(s(), id("receipt"), owner()).await,
+            Ok(StatusCode::NO_CONTENT)
+        ));
+        assert!(matches!(
+            delete_session(s(), id("session"), owner()).await,
+            Ok(StatusCode::NO_CONTENT)
+        ));
+    }
+
+    #[test]
+    fn test_session_query_matches() {
+        let info = SessionInfo::new("image".to_string(), Some("key".to_string()));
+        let created_at = info.created_at_unix();
+
+        assert!(SessionQuery::default().matches(&info));
+        assert!(SessionQuery {
+            status: Some(SessionStatus::Running),
+            image_id: Some("image".to_string()),
+            created_after: Some(created_at),
+            created_before: Some(created_at + 1),
+            ..Default::default()
+        }
+        .matches(&info));
+
+        assert!(!SessionQuery {
+            status: Some(SessionStatus::Succeeded),
+            ..Default::default()
+        }
+        .matches(&info));
+        assert!(!SessionQuery {
+            image_id: Some("other".to_string()),
+            ..Default::default()
+        }
+        .matches(&info));
+        assert!(!SessionQuery {
+            created_before: Some(created_at),
+            ..Default::default()
+        }
+        .matches(&info));
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions_of_caller() {
+        let state: AppState = Arc::new(RwLock::new(BonsaiState::new(Duration::from_secs(60))));
+        for (session_id, tenant) in [
+            ("mine", Some("key")),
+            ("theirs", Some("other")),
+            ("anon", None),
+        ] {
+            state.write().unwrap().put_session_info(
+                session_id.to_string(),
+                SessionInfo::new("image".to_string(), tenant.map(str::to_string)),
+            );
+        }
+        let list = |headers| {
+            list_sessions(
+                State(Arc::clone(&state)),
+                headers,
+                Ok(Query(SessionQuery::default())),
+            )
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, "key".parse().unwrap());
+        let Json(page) = list(headers).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.sessions[0].uuid, "mine");
+
+        let Json(page) = list(HeaderMap::new()).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.sessions[0].uuid, "anon");
+    }
+
+    #[test]
+    fn test_session_query_parses_status() {
+        let Query(query) = Query::<SessionQuery>::try_from_uri(
+            &"/sessions?status=TIMED_OUT&limit=5".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(query.status, Some(SessionStatus::TimedOut));
+        assert_eq!(query.limit, Some(5));
+        assert!(
+            Query::<SessionQuery>::try_from_uri(&"/sessions?status=DONE".parse().unwrap()).is_err()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+};
+
+pub(crate) type SharedSessionLog = Arc<Mutex<SessionLog>>;
+
+/// Bounded buffer holding everything a guest wrote to stdout and stderr.
+///
+/// Once `capacity` is exceeded the oldest bytes are dropped, so the most recent
+/// output (usually the interesting part when a guest fails) is kept.
+#[derive(Debug)]
+pub(crate) struct SessionLog {
+    capacity: usize,
+    buffer: VecDeque<u8>,
+    truncated: usize,
+}
+
+impl SessionLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffer: VecDeque::new(),
+            truncated: 0,
+        }
+    }
+
+    pub(crate) fn shared(capacity: usize) -> SharedSessionLog {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    pub(crate) fn append(&mut self, data: &[u8]) {
+        self.buffer.extend(data);
+        if self.buffer.len() > self.capacity {
+            let excess = self.buffer.len() - self.capacity;
+            self.buffer.drain(..excess);
+            self.truncated += excess;
+        }
+    }
+
+    /// Returns the buffered output as text, noting how many bytes were dropped.
+    pub(crate) fn contents(&self) -> String {
+        let (front, back) = self.buffer.as_slices();
+        let text = String::from_utf8_lossy(&[front, back].concat()).into_owned();
+        if self.truncated > 0 {
+            format!("[{} bytes truncated]\n{text}", self.truncated)
+        } else {
+            text
+        }
+    }
+}
+
+/// [`io::Write`] adapter passed to the executor as guest stdout/stderr.
+#[derive(Clone)]
+pub(crate) struct SessionLogWriter(pub SharedSessionLog);
+
+impl io::Write for SessionLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("session log lock poisoned"))?
+            .append(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_append_within_capacity() {
+        let mut log = SessionLog::new(16);
+        log.append(b"hello ");
+        log.append(b"world");
+        assert_eq!(log.contents(), "hello world");
+    }
+
+    #[test]
+    fn test_append_keeps_most_recent_output() {
+        let mut log = SessionLog::new(8);
+        log.append(b"0123456789");
+        log.append(b"ab");
+        assert_eq!(log.contents(), "[4 bytes truncated]\n456789ab");
+    }
+
+    #[test]
+    fn test_writer_appends_to_shared_log() {
+        let log = SessionLog::shared(64);
+        let mut stdout = SessionLogWriter(Arc::clone(&log));
+        let mut stderr = SessionLogWriter(Arc::clone(&log));
+        writeln!(stdout, "out").unwrap();
+        writeln!(stderr, "err").unwrap();
+        assert_eq!(log.lock().unwrap().contents(), "out\nerr\n");
+    }
+}
@@ -14,17 +14,24 @@
 
 use std::{
     collections::HashMap,
+    fmt,
     sync::{Arc, RwLock},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use risc0_zkvm::SessionStats;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{blob::Blob, error::Error, quota::CycleLedger, session_log::SharedSessionLog};
 
 pub(crate) type AppState = Arc<RwLock<BonsaiState>>;
 
 pub(crate) struct EntryWithTimestamp<T> {
     pub(crate) data: T,
     pub(crate) created_at: Instant,
+    /// API key of the uploader, counted against its storage quota
+    pub(crate) tenant: Option<String>,
 }
 
 impl<T> EntryWithTimestamp<T> {
@@ -32,19 +39,38 @@ impl<T> EntryWithTimestamp<T> {
         Self {
             data,
             created_at: Instant::now(),
+            tenant: None,
+        }
+    }
+
+    fn owned_by(data: T, tenant: Option<String>) -> Self {
+        Self {
+            tenant,
+            ..Self::new(data)
         }
     }
 
     fn is_expired(&self, ttl: Duration) -> bool {
         self.created_at.elapsed() > ttl
     }
+
+    /// Creation time as seconds since the Unix epoch.
+    pub(crate) fn created_at_unix(&self) -> u64 {
+        (SystemTime::now() - self.created_at.elapsed())
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SessionStatus {
     Running,
     Succeeded,
     Failed,
+    TimedOut,
+    Aborted,
 }
 
 impl ToString for SessionStatus {
@@ -53,20 +79,113 @@ impl ToString for SessionStatus {
             SessionStatus::Running => "RUNNING".to_string(),
             SessionStatus::Succeeded => "SUCCEEDED".to_string(),
             SessionStatus::Failed => "FAILED".to_string(),
+            SessionStatus::TimedOut => "TIMED_OUT".to_string(),
+            SessionStatus::Aborted => "ABORTED".to_string(),
+        }
+    }
+}
+
+/// Kinds of stored resources, used to tell evicted ids apart from unknown ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Resource {
+    Image,
+    Input,
+    Session,
+    Receipt,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Image => write!(f, "Image"),
+            Resource::Input => write!(f, "Input"),
+            Resource::Session => write!(f, "Session"),
+            Resource::Receipt => write!(f, "Receipt"),
         }
     }
 }
 
+/// A session's status together with the metadata used to list and search sessions.
+#[derive(Debug)]
+pub(crate) struct SessionInfo {
+    pub(crate) status: SessionStatus,
+    pub(crate) stats: Option<SessionStats>,
+    /// Why the session failed or timed out
+    pub(crate) error_msg: Option<String>,
+    pub(crate) image_id: String,
+    /// API key the session was created with, if any
+    pub(crate) tenant: Option<String>,
+    pub(crate) created_at: SystemTime,
+    /// When the prover picked the session up from the queue
+    pub(crate) started_at: Option<Instant>,
+    /// Time from the prover picking the session up until it finished
+    pub(crate) duration: Option<Duration>,
+    /// Cycles of its creator's daily budget held for the session while it runs
+    pub(crate) reserved_cycles: u64,
+    /// Cancelled when the session is aborted through `/sessions/stop`
+    pub(crate) cancel: CancellationToken,
+}
+
+impl SessionInfo {
+    pub(crate) fn new(image_id: String, tenant: Option<String>) -> Self {
+        Self {
+            status: SessionStatus::Running,
+            stats: None,
+            error_msg: None,
+            image_id,
+            tenant,
+            created_at: SystemTime::now(),
+            started_at: None,
+            duration: None,
+            reserved_cycles: 0,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Creation time as seconds since the Unix epoch.
+    pub(crate) fn created_at_unix(&self) -> u64 {
+        self.created_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// Number and total size of the stored entries of one kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Usage {
+    pub(crate) count: usize,
+    pub(crate) bytes: u64,
+    /// Bytes spilled to temporary files rather than held in memory
+    pub(crate) on_disk_bytes: u64,
+}
+
+/// Image, input and assumption receipts a queued session still needs from the state.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingSession {
+    pub(crate) image_id: String,
+    pub(crate) input_id: String,
+    pub(crate) assumptions: Vec<String>,
+}
+
 pub(crate) struct BonsaiState {
     pub(crate) ttl: Duration,
     // ImageID - MemoryImage
-    pub(crate) images: HashMap<String, EntryWithTimestamp<Vec<u8>>>,
+    pub(crate) images: HashMap<String, EntryWithTimestamp<Blob>>,
     // InputID - input
-    pub(crate) inputs: HashMap<String, EntryWithTimestamp<Vec<u8>>>,
-    // SessionID - Status
-    pub(crate) sessions: HashMap<String, EntryWithTimestamp<(SessionStatus, Option<SessionStats>)>>,
+    pub(crate) inputs: HashMap<String, EntryWithTimestamp<Blob>>,
+    // SessionID - Status and metadata
+    pub(crate) sessions: HashMap<String, EntryWithTimestamp<SessionInfo>>,
     // SessionID - Receipts
-    pub(crate) receipts: HashMap<String, EntryWithTimestamp<Vec<u8>>>,
+    pub(crate) receipts: HashMap<String, EntryWithTimestamp<Blob>>,
+    // SessionID - Guest stdout/stderr
+    pub(crate) logs: HashMap<String, EntryWithTimestamp<SharedSessionLog>>,
+    // SessionID - Ids a queued session has yet to read
+    pub(crate) pending: HashMap<String, PendingSession>,
+    // Ids removed by `cleanup_expired`, kept for another TTL to answer with 410 Gone
+    pub(crate) evicted: HashMap<(Resource, String), EntryWithTimestamp<()>>,
+    // Executor cycles of finished sessions per tenant
+    pub(crate) cycles: CycleLedger,
 }
 
 impl BonsaiState {
@@ -77,71 +196,407 @@ impl BonsaiState {
             inputs: HashMap::new(),
             sessions: HashMap::new(),
             receipts: HashMap::new(),
+            logs: HashMap::new(),
+            pending: HashMap::new(),
+            evicted: HashMap::new(),
+            cycles: CycleLedger::default(),
         }
     }
 
-    pub(crate) fn put_image(&mut self, image_id: String, image: Vec<u8>) -> Option<Vec<u8>> {
+    pub(crate) fn put_image(
+        &mut self,
+        image_id: String,
+        image: Blob,
+        tenant: Option<String>,
+    ) -> Option<Blob> {
         self.images
-            .insert(image_id, EntryWithTimestamp::new(image))
+            .insert(image_id, EntryWithTimestamp::owned_by(image, tenant))
             .map(|e| e.data)
     }
 
-    pub(crate) fn get_image(&self, image_id: impl AsRef<str>) -> Option<Vec<u8>> {
-        self.images.get(image_id.as_ref()).map(|e| e.data.clone())
+    pub(crate) fn get_image(&self, image_id: impl AsRef<str>) -> Option<Blob> {
+        self.images
+            .get(image_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data.clone())
+    }
+
+    pub(crate) fn remove_image(&mut self, image_id: impl AsRef<str>) -> Option<Blob> {
+        self.images
+            .remove(image_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data)
     }
 
-    pub(crate) fn put_input(&mut self, input_id: String, input: Vec<u8>) -> Option<Vec<u8>> {
+    pub(crate) fn remove_input(&mut self, input_id: impl AsRef<str>) -> Option<Blob> {
         self.inputs
-            .insert(input_id, EntryWithTimestamp::new(input))
+            .remove(input_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data)
+    }
+
+    /// Removes a session together with its receipt and logs.
+    pub(crate) fn remove_session(&mut self, session_id: impl AsRef<str>) -> Option<SessionInfo> {
+        let session_id = session_id.as_ref();
+        self.receipts.remove(session_id);
+        self.logs.remove(session_id);
+        self.sessions
+            .remove(session_id)
+            .filter(|e| !e.is_expired(self.ttl))
             .map(|e| e.data)
     }
 
-    pub(crate) fn get_input(&self, input_id: impl AsRef<str>) -> Option<Vec<u8>> {
-        self.inputs.get(input_id.as_ref()).map(|e| e.data.clone())
+    pub(crate) fn remove_receipt(&mut self, receipt_id: impl AsRef<str>) -> Option<Blob> {
+        self.receipts
+            .remove(receipt_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data)
+    }
+
+    /// Marks the input and assumptions of a queued session as in use until
+    /// [`Self::release`] is called for it.
+    pub(crate) fn reserve(&mut self, session_id: String, pending: PendingSession) {
+        self.pending.insert(session_id, pending);
+    }
+
+    pub(crate) fn release(&mut self, session_id: impl AsRef<str>) {
+        self.pending.remove(session_id.as_ref());
     }
 
+    /// Returns true if a queued session still needs the given image, input or receipt.
+    pub(crate) fn in_use(&self, resource: Resource, id: impl AsRef<str>) -> bool {
+        let id = id.as_ref();
+        self.pending.values().any(|pending| match resource {
+            Resource::Image => pending.image_id == id,
+            Resource::Input => pending.input_id == id,
+            Resource::Receipt => pending.assumptions.iter().any(|r| r == id),
+            Resource::Session => false,
+        })
+    }
+
+    /// Returns true if the given image, input, receipt or session was created with an
+    /// API key other than `tenant`. Entries stored without a key belong to everyone.
+    pub(crate) fn owned_by_other(
+        &self,
+        resource: Resource,
+        id: impl AsRef<str>,
+        tenant: Option<&str>,
+    ) -> bool {
+        let id = id.as_ref();
+        let owner = match resource {
+            Resource::Image => self.images.get(id).map(|e| &e.tenant),
+            Resource::Input => self.inputs.get(id).map(|e| &e.tenant),
+            Resource::Receipt => self.receipts.get(id).map(|e| &e.tenant),
+            Resource::Session => self.sessions.get(id).map(|e| &e.data.tenant),
+        };
+        owner
+            .and_then(Option::as_deref)
+            .is_some_and(|owner| Some(owner) != tenant)
+    }
+
+    /// Returns all stored, non-expired sessions.
+    pub(crate) fn list_sessions(&self) -> impl Iterator<Item = (&String, &SessionInfo)> {
+        self.sessions
+            .iter()
+            .filter(|(_, e)| !e.is_expired(self.ttl))
+            .map(|(id, e)| (id, &e.data))
+    }
+
+    /// Returns how many unexpired entries of the given kind are stored and how much space
+    /// they take.
+    ///
+    /// Sessions and their logs are not counted; they are only metadata.
+    pub(crate) fn usage(&self, resource: Resource) -> Usage {
+        let map = match resource {
+            Resource::Image => &self.images,
+            Resource::Input => &self.inputs,
+            Resource::Receipt => &self.receipts,
+            Resource::Session => return Usage::default(),
+        };
+        map.values()
+            .filter(|entry| !entry.is_expired(self.ttl))
+            .fold(Usage::default(), |mut usage, entry| {
+                usage.count += 1;
+                usage.bytes += entry.data.len();
+                if entry.data.is_on_disk() {
+                    usage.on_disk_bytes += entry.data.len();
+                }
+                usage
+            })
+    }
+
+    /// Returns the number of queued or running sessions created by `tenant`.
+    pub(crate) fn running_sessions(&self, tenant: Option<&str>) -> usize {
+        self.list_sessions()
+            .filter(|(_, info)| {
+                info.status == SessionStatus::Running && info.tenant.as_deref() == tenant
+            })
+            .count()
+    }
+
+    /// Returns the cycles of `tenant`'s daily budget held by its queued or running
+    /// sessions.
+    pub(crate) fn reserved_cycles(&self, tenant: Option<&str>) -> u64 {
+        self.list_sessions()
+            .filter(|(_, info)| {
+                info.status == SessionStatus::Running && info.tenant.as_deref() == tenant
+            })
+            .map(|(_, info)| info.reserved_cycles)
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Returns the size of the images, inputs and receipts stored by `tenant`,
+    /// leaving out `replaced`, an entry about to be overwritten by an upload.
+    pub(crate) fn stored_bytes(
+        &self,
+        tenant: Option<&str>,
+        replaced: Option<(Resource, &str)>,
+    ) -> u64 {
+        [
+            (Resource::Image, &self.images),
+            (Resource::Input, &self.inputs),
+            (Resource::Receipt, &self.receipts),
+        ]
+        .into_iter()
+        .flat_map(|(resource, map)| map.iter().map(move |(id, e)| (resource, id, e)))
+        .filter(|(resource, id, e)| {
+            e.tenant.as_deref() == tenant
+                && !e.is_expired(self.ttl)
+                && replaced != Some((*resource, id.as_str()))
+        })
+        .map(|(_, _, e)| e.data.len())
+        .sum()
+    }
+
+    /// Returns all stored, non-expired images.
+    pub(crate) fn list_images(&self) -> impl Iterator<Item = (&String, &EntryWithTimestamp<Blob>)> {
+        self.images.iter().filter(|(_, e)| !e.is_expired(self.ttl))
+    }
+
+    pub(crate) fn put_input(
+        &mut self,
+        input_id: String,
+        input: Blob,
+        tenant: Option<String>,
+    ) -> Option<Blob> {
+        self.inputs
+            .insert(input_id, EntryWithTimestamp::owned_by(input, tenant))
+            .map(|e| e.data)
+    }
+
+    pub(crate) fn get_input(&self, input_id: impl AsRef<str>) -> Option<Blob> {
+        self.inputs
+            .get(input_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data.clone())
+    }
+
+    pub(crate) fn put_session_info(
+        &mut self,
+        session_id: String,
+        info: SessionInfo,
+    ) -> Option<SessionInfo> {
+        self.sessions
+            .insert(session_id, EntryWithTimestamp::new(info))
+            .map(|e| e.data)
+    }
+
+    /// Updates the status of a session, keeping the metadata it was created with.
+    ///
+    /// The TTL restarts from the update, so finished sessions stay available for a
+    /// full TTL regardless of how long proving took. Aborted sessions keep their
+    /// status so a proof finishing after the abort cannot overwrite it.
     pub(crate) fn put_session(
         &mut self,
         session_id: String,
         status: SessionStatus,
         stats: Option<SessionStats>,
-    ) -> Option<(SessionStatus, Option<SessionStats>)> {
-        self.sessions
-            .insert(session_id, EntryWithTimestamp::new((status, stats)))
-            .map(|e| e.data)
+    ) -> Option<SessionInfo> {
+        let mut info = match self.sessions.remove(&session_id) {
+            Some(entry) if entry.data.status == SessionStatus::Aborted => {
+                self.sessions.insert(session_id, entry);
+                return None;
+            }
+            Some(entry) => entry.data,
+            None => SessionInfo::new(String::new(), None),
+        };
+        if status != SessionStatus::Running {
+            info.duration = info.started_at.map(|started_at| started_at.elapsed());
+        }
+        if let Some(stats) = &stats {
+            self.cycles.record(info.tenant.clone(), stats.total_cycles);
+        }
+        info.status = status;
+        info.stats = stats;
+        self.put_session_info(session_id, info)
     }
 
-    pub(crate) fn get_session(
-        &self,
-        session_id: impl AsRef<str>,
-    ) -> Option<&(SessionStatus, Option<SessionStats>)> {
-        self.sessions.get(session_id.as_ref()).map(|e| &e.data)
+    /// Charges the cycles a session used without succeeding to its creator's budget.
+    pub(crate) fn charge_cycles(&mut self, session_id: impl AsRef<str>, cycles: u64) {
+        if let Some(entry) = self.sessions.get(session_id.as_ref()) {
+            self.cycles.record(entry.data.tenant.clone(), cycles);
+        }
+    }
+
+    /// Records why a session failed, to be reported to clients polling its status.
+    pub(crate) fn put_session_error(&mut self, session_id: impl AsRef<str>, error_msg: String) {
+        if let Some(entry) = self.sessions.get_mut(session_id.as_ref()) {
+            entry.data.error_msg = Some(error_msg);
+        }
     }
 
-    pub(crate) fn put_receipt(&mut self, session_id: String, receipt: Vec<u8>) -> Option<Vec<u8>> {
+    /// Records that the prover has started working on a session.
+    pub(crate) fn mark_session_started(&mut self, session_id: impl AsRef<str>) {
+        if let Some(entry) = self.sessions.get_mut(session_id.as_ref()) {
+            entry.data.started_at = Some(Instant::now());
+        }
+    }
+
+    /// Marks a running session as aborted and signals the prover to stop working on it.
+    ///
+    /// Returns false if the session had already finished.
+    pub(crate) fn abort_session(&mut self, session_id: impl AsRef<str>) -> bool {
+        let session_id = session_id.as_ref();
+        let Some(entry) = self.sessions.get_mut(session_id) else {
+            return false;
+        };
+        let info = &mut entry.data;
+        if info.status != SessionStatus::Running {
+            return false;
+        }
+        info.status = SessionStatus::Aborted;
+        info.duration = info.started_at.map(|started_at| started_at.elapsed());
+        info.cancel.cancel();
+        self.pending.remove(session_id);
+        true
+    }
+
+    /// Fails every running session with `error_msg` and signals the prover to stop
+    /// working on them, returning how many failed.
+    pub(crate) fn fail_running(&mut self, error_msg: &str) -> usize {
+        let mut failed = 0;
+        for (session_id, entry) in self.sessions.iter_mut() {
+            let info = &mut entry.data;
+            if info.status != SessionStatus::Running {
+                continue;
+            }
+            info.status = SessionStatus::Failed;
+            info.error_msg = Some(error_msg.to_string());
+            info.duration = info.started_at.map(|started_at| started_at.elapsed());
+            info.cancel.cancel();
+            self.pending.remove(session_id);
+            failed += 1;
+        }
+        failed
+    }
+
+    /// Aborts every session still waiting in the queue, returning how many were aborted.
+    pub(crate) fn drain_queued(&mut self) -> usize {
+        let queued: Vec<String> = self
+            .list_sessions()
+            .filter(|(_, info)| info.status == SessionStatus::Running && info.started_at.is_none())
+            .map(|(id, _)| id.clone())
+            .collect();
+        queued.iter().filter(|id| self.abort_session(id)).count()
+    }
+
+    pub(crate) fn get_session(&self, session_id: impl AsRef<str>) -> Option<&SessionInfo> {
+        self.sessions
+            .get(session_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| &e.data)
+    }
+
+    pub(crate) fn put_receipt(
+        &mut self,
+        session_id: String,
+        receipt: Blob,
+        tenant: Option<String>,
+    ) -> Option<Blob> {
         self.receipts
-            .insert(session_id, EntryWithTimestamp::new(receipt))
+            .insert(session_id, EntryWithTimestamp::owned_by(receipt, tenant))
             .map(|e| e.data)
     }
 
-    pub(crate) fn get_receipt(&self, session_id: impl AsRef<str>) -> Option<Vec<u8>> {
+    pub(crate) fn get_receipt(&self, session_id: impl AsRef<str>) -> Option<Blob> {
         self.receipts
             .get(session_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
+            .map(|e| e.data.clone())
+    }
+
+    pub(crate) fn put_session_log(
+        &mut self,
+        session_id: String,
+        log: SharedSessionLog,
+    ) -> Option<SharedSessionLog> {
+        self.logs
+            .insert(session_id, EntryWithTimestamp::new(log))
+            .map(|e| e.data)
+    }
+
+    pub(crate) fn get_session_log(&self, session_id: impl AsRef<str>) -> Option<SharedSessionLog> {
+        self.logs
+            .get(session_id.as_ref())
+            .filter(|e| !e.is_expired(self.ttl))
             .map(|e| e.data.clone())
     }
 
-    pub(crate) fn cleanup_expired(&mut self) {
+    /// Returns the error for a lookup that found nothing: `Gone` if the id was
+    /// evicted by [`Self::cleanup_expired`], otherwise the resource's not-found error.
+    pub(crate) fn missing(&self, resource: Resource, id: impl AsRef<str>) -> Error {
+        let id = id.as_ref().to_string();
+        if self.evicted.contains_key(&(resource, id.clone())) {
+            return Error::Gone(resource, id);
+        }
+        match resource {
+            Resource::Image => Error::ImageNotFound(id),
+            Resource::Input => Error::InputNotFound(id),
+            Resource::Session => Error::SessionNotFound(id),
+            Resource::Receipt => Error::ReceiptNotFound(id),
+        }
+    }
+
+    /// Removes expired entries, returning how many were removed.
+    pub(crate) fn cleanup_expired(&mut self) -> usize {
         let ttl = self.ttl;
-        self.images.retain(|_, entry| !entry.is_expired(ttl));
-        self.inputs.retain(|_, entry| !entry.is_expired(ttl));
-        self.sessions.retain(|_, entry| !entry.is_expired(ttl));
-        self.receipts.retain(|_, entry| !entry.is_expired(ttl));
+        let mut evicted = vec![];
+        evicted.extend(evict(&mut self.images, ttl, Resource::Image));
+        evicted.extend(evict(&mut self.inputs, ttl, Resource::Input));
+        evicted.extend(evict(&mut self.sessions, ttl, Resource::Session));
+        evicted.extend(evict(&mut self.receipts, ttl, Resource::Receipt));
+        self.logs.retain(|_, entry| !entry.is_expired(ttl));
+
+        self.evicted.retain(|_, entry| !entry.is_expired(ttl));
+        let count = evicted.len();
+        for key in evicted {
+            self.evicted.insert(key, EntryWithTimestamp::new(()));
+        }
+        count
     }
 }
 
+/// Removes expired entries from `map`, returning their keys.
+fn evict<T>(
+    map: &mut HashMap<String, EntryWithTimestamp<T>>,
+    ttl: Duration,
+    resource: Resource,
+) -> Vec<(Resource, String)> {
+    let mut evicted = vec![];
+    map.retain(|id, entry| {
+        let expired = entry.is_expired(ttl);
+        if expired {
+            evicted.push((resource, id.clone()));
+        }
+        !expired
+    });
+    evicted
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::session_log::SessionLog;
     use std::thread::sleep;
 
     #[test]
@@ -164,10 +619,10 @@ mod tests {
         let mut state = BonsaiState::new(ttl);
 
         // Add some entries
-        state.put_image("image1".to_string(), vec![1, 2, 3]);
-        state.put_input("input1".to_string(), vec![4, 5, 6]);
+        state.put_image("image1".to_string(), vec![1, 2, 3].into(), None);
+        state.put_input("input1".to_string(), vec![4, 5, 6].into(), None);
         state.put_session("session1".to_string(), SessionStatus::Running, None);
-        state.put_receipt("receipt1".to_string(), vec![7, 8, 9]);
+        state.put_receipt("receipt1".to_string(), vec![7, 8, 9].into(), None);
 
         // Verify all entries exist
         assert!(state.get_image("image1").is_some());
@@ -179,8 +634,8 @@ mod tests {
         sleep(Duration::from_millis(150));
 
         // Add new entries that should not expire
-        state.put_image("image2".to_string(), vec![10, 11, 12]);
-        state.put_input("input2".to_string(), vec![13, 14, 15]);
+        state.put_image("image2".to_string(), vec![10, 11, 12].into(), None);
+        state.put_input("input2".to_string(), vec![13, 14, 15].into(), None);
 
         // Run cleanup
         state.cleanup_expired();
@@ -202,14 +657,14 @@ mod tests {
         let mut state = BonsaiState::new(ttl);
 
         // Add first batch of entries
-        state.put_image("old_image".to_string(), vec![1, 2, 3]);
-        state.put_input("old_input".to_string(), vec![4, 5, 6]);
+        state.put_image("old_image".to_string(), vec![1, 2, 3].into(), None);
+        state.put_input("old_input".to_string(), vec![4, 5, 6].into(), None);
 
         // Wait half the TTL
         sleep(Duration::from_millis(100));
 
         // Add second batch of entries
-        state.put_image("new_image".to_string(), vec![7, 8, 9]);
+        state.put_image("new_image".to_string(), vec![7, 8, 9].into(), None);
         state.put_session("new_session".to_string(), SessionStatus::Running, None);
 
         // Wait for first batch to expire but not second batch
@@ -227,16 +682,264 @@ mod tests {
         assert!(state.get_session("new_session").is_some());
     }
 
+    #[test]
+    fn test_expired_entries_are_not_returned() {
+        let ttl = Duration::from_millis(100);
+        let mut state = BonsaiState::new(ttl);
+
+        state.put_session("session".to_string(), SessionStatus::Running, None);
+        sleep(Duration::from_millis(150));
+
+        // expired but not yet evicted: unknown rather than gone
+        assert!(state.get_session("session").is_none());
+        assert!(matches!(
+            state.missing(Resource::Session, "session"),
+            Error::SessionNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_missing_distinguishes_evicted_from_unknown() {
+        let ttl = Duration::from_millis(100);
+        let mut state = BonsaiState::new(ttl);
+
+        state.put_receipt("receipt".to_string(), vec![1, 2, 3].into(), None);
+        sleep(Duration::from_millis(150));
+        state.cleanup_expired();
+
+        assert!(matches!(
+            state.missing(Resource::Receipt, "receipt"),
+            Error::Gone(Resource::Receipt, _)
+        ));
+        assert!(matches!(
+            state.missing(Resource::Receipt, "unknown"),
+            Error::ReceiptNotFound(_)
+        ));
+        // the same id is unrelated for other resources
+        assert!(matches!(
+            state.missing(Resource::Session, "receipt"),
+            Error::SessionNotFound(_)
+        ));
+
+        // tombstones are dropped after another TTL
+        sleep(Duration::from_millis(150));
+        state.cleanup_expired();
+        assert!(matches!(
+            state.missing(Resource::Receipt, "receipt"),
+            Error::ReceiptNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_list_and_remove_images() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+
+        state.put_image("image1".to_string(), vec![1, 2, 3].into(), None);
+        state.put_image("image2".to_string(), vec![4, 5].into(), None);
+
+        let mut ids: Vec<_> = state.list_images().map(|(id, _)| id.clone()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["image1", "image2"]);
+
+        assert!(state.remove_image("image1").is_some());
+        assert!(state.remove_image("image1").is_none());
+        assert!(state.get_image("image1").is_none());
+        assert_eq!(state.list_images().count(), 1);
+    }
+
+    #[test]
+    fn test_pending_sessions_keep_data_in_use() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        state.reserve(
+            "session".to_string(),
+            PendingSession {
+                image_id: "image".to_string(),
+                input_id: "input".to_string(),
+                assumptions: vec!["receipt".to_string()],
+            },
+        );
+
+        assert!(state.in_use(Resource::Image, "image"));
+        assert!(state.in_use(Resource::Input, "input"));
+        assert!(state.in_use(Resource::Receipt, "receipt"));
+        assert!(!state.in_use(Resource::Input, "other"));
+
+        state.release("session");
+        assert!(!state.in_use(Resource::Image, "image"));
+        assert!(!state.in_use(Resource::Input, "input"));
+        assert!(!state.in_use(Resource::Receipt, "receipt"));
+    }
+
+    #[test]
+    fn test_remove_session_purges_receipt_and_logs() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        state.put_session("session".to_string(), SessionStatus::Succeeded, None);
+        state.put_receipt("session".to_string(), vec![1, 2, 3].into(), None);
+        state.put_session_log("session".to_string(), SessionLog::shared(16));
+
+        assert!(state.remove_session("session").is_some());
+        assert!(state.get_session("session").is_none());
+        assert!(state.get_receipt("session").is_none());
+        assert!(state.get_session_log("session").is_none());
+        assert!(state.remove_session("session").is_none());
+    }
+
+    #[test]
+    fn test_put_session_keeps_metadata() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        state.put_session_info(
+            "session".to_string(),
+            SessionInfo::new("image".to_string(), Some("key".to_string())),
+        );
+        state.mark_session_started("session");
+        state.put_session("session".to_string(), SessionStatus::Failed, None);
+        state.put_session_error("session", "guest panicked".to_string());
+
+        let info = state.get_session("session").unwrap();
+        assert_eq!(info.status, SessionStatus::Failed);
+        assert_eq!(info.error_msg.as_deref(), Some("guest panicked"));
+        assert_eq!(info.image_id, "image");
+        assert_eq!(info.tenant.as_deref(), Some("key"));
+        assert!(info.duration.is_some());
+        assert_eq!(state.list_sessions().count(), 1);
+    }
+
+    #[test]
+    fn test_abort_session() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        state.put_session_info(
+            "session".to_string(),
+            SessionInfo::new("image".to_string(), None),
+        );
+        state.reserve(
+            "session".to_string(),
+            PendingSession {
+                image_id: "image".to_string(),
+                input_id: "input".to_string(),
+                assumptions: vec![],
+            },
+        );
+
+        assert!(state.abort_session("session"));
+        assert!(!state.abort_session("session"));
+        assert!(!state.in_use(Resource::Input, "input"));
+        let info = state.get_session("session").unwrap();
+        assert_eq!(info.status, SessionStatus::Aborted);
+        assert!(info.cancel.is_cancelled());
+
+        // a proof finishing after the abort does not change the status
+        state.put_session("session".to_string(), SessionStatus::Succeeded, None);
+        assert_eq!(
+            state.get_session("session").unwrap().status,
+            SessionStatus::Aborted
+        );
+    }
+
+    #[test]
+    fn test_fail_running() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        for id in ["running", "done"] {
+            state.put_session_info(id.to_string(), SessionInfo::new("image".to_string(), None));
+        }
+        state.mark_session_started("running");
+        state.reserve(
+            "running".to_string(),
+            PendingSession {
+                image_id: "image".to_string(),
+                input_id: "input".to_string(),
+                assumptions: vec![],
+            },
+        );
+        state.put_session("done".to_string(), SessionStatus::Succeeded, None);
+
+        assert_eq!(state.fail_running("server shut down"), 1);
+        assert!(!state.in_use(Resource::Input, "input"));
+        let info = state.get_session("running").unwrap();
+        assert_eq!(info.status, SessionStatus::Failed);
+        assert_eq!(info.error_msg.as_deref(), Some("server shut down"));
+        assert!(info.cancel.is_cancelled());
+        assert_eq!(
+            state.get_session("done").unwrap().status,
+            SessionStatus::Succeeded
+        );
+    }
+
+    #[test]
+    fn test_drain_queued() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        for id in ["queued", "running"] {
+            state.put_session_info(id.to_string(), SessionInfo::new("image".to_string(), None));
+        }
+        state.mark_session_started("running");
+
+        assert_eq!(state.drain_queued(), 1);
+        assert_eq!(
+            state.get_session("queued").unwrap().status,
+            SessionStatus::Aborted
+        );
+        assert_eq!(
+            state.get_session("running").unwrap().status,
+            SessionStatus::Running
+        );
+        assert_eq!(state.drain_queued(), 0);
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        state.put_image("image1".to_string(), vec![1, 2, 3].into(), None);
+        state.put_image("image2".to_string(), vec![4, 5].into(), None);
+
+        assert_eq!(
+            state.usage(Resource::Image),
+            Usage {
+                count: 2,
+                bytes: 5,
+                on_disk_bytes: 0,
+            }
+        );
+        assert_eq!(state.usage(Resource::Input), Usage::default());
+
+        // expired entries waiting for the cleanup are not counted
+        state.ttl = Duration::ZERO;
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(state.usage(Resource::Image), Usage::default());
+    }
+
+    #[test]
+    fn test_tenant_usage() {
+        let mut state = BonsaiState::new(Duration::from_secs(10));
+        let key = || Some("key".to_string());
+        state.put_image("image".to_string(), vec![1, 2, 3].into(), key());
+        state.put_input("input".to_string(), vec![4, 5].into(), key());
+        state.put_receipt("receipt".to_string(), vec![6].into(), None);
+
+        assert_eq!(state.stored_bytes(Some("key"), None), 5);
+        assert_eq!(
+            state.stored_bytes(Some("key"), Some((Resource::Image, "image"))),
+            2
+        );
+        assert_eq!(state.stored_bytes(None, None), 1);
+
+        state.put_session_info(
+            "session".to_string(),
+            SessionInfo::new("image".to_string(), key()),
+        );
+        assert_eq!(state.running_sessions(Some("key")), 1);
+        assert_eq!(state.running_sessions(None), 0);
+        assert_eq!(state.cycles.last_day(Some("key")), 0);
+    }
+
     #[test]
     fn test_no_cleanup_when_not_expired() {
         let ttl = Duration::from_secs(10); // Long TTL
         let mut state = BonsaiState::new(ttl);
 
         // Add entries
-        state.put_image("image".to_string(), vec![1, 2, 3]);
-        state.put_input("input".to_string(), vec![4, 5, 6]);
+        state.put_image("image".to_string(), vec![1, 2, 3].into(), None);
+        state.put_input("input".to_string(), vec![4, 5, 6].into(), None);
         state.put_session("session".to_string(), SessionStatus::Running, None);
-        state.put_receipt("receipt".to_string(), vec![7, 8, 9]);
+        state.put_receipt("receipt".to_string(), vec![7, 8, 9].into(), None);
 
         // Run cleanup immediately
         state.cleanup_expired();
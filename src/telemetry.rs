@@ -0,0 +1,91 @@
+use axum::{
+    extract::ConnectInfo,
+    http::{HeaderMap, Request},
+};
+use opentelemetry::{global, propagation::Extractor, Context};
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{forwarded, listener::Peer, trusted_proxies::TrustedProxies};
+
+/// Reads propagation fields (e.g. W3C `traceparent`/`tracestate`) from request headers.
+pub(crate) struct HeaderExtractor<'a>(pub &'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Extracts the caller's trace context using the globally installed propagator.
+///
+/// Returns an empty context when no propagator is installed or the headers carry
+/// no trace context, in which case spans simply start a new trace.
+pub(crate) fn extract_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// Creates the per-request span for `TraceLayer`, parented to the client's trace if any.
+///
+/// `client` is the originating client, looked up through forwarded headers set by
+/// `trusted_proxies`.
+pub(crate) fn make_request_span<B>(request: &Request<B>, trusted_proxies: &TrustedProxies) -> Span {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<Peer>>()
+        .map(|ConnectInfo(peer)| forwarded::client(request.headers(), *peer, trusted_proxies));
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = request
+            .headers()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default(),
+        client = client.as_deref().unwrap_or_default(),
+    );
+    span.set_parent(extract_context(request.headers()));
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use opentelemetry::{
+        propagation::TextMapPropagator,
+        trace::{TraceContextExt, TraceId},
+    };
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    #[test]
+    fn test_extract_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+
+        let cx = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        let span = cx.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_without_traceparent() {
+        let headers = HeaderMap::new();
+        let cx = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        assert!(!cx.span().span_context().is_valid());
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context};
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use std::{path::Path, path::PathBuf, sync::Arc};
+
+/// Certificate and key files for serving HTTPS.
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    /// PEM file with the server certificate chain
+    pub cert_path: PathBuf,
+    /// PEM file with the server private key
+    pub key_path: PathBuf,
+    /// PEM file with the CA certificates that client certificates must chain to.
+    /// When set, clients without a valid certificate are rejected.
+    pub client_ca_path: Option<PathBuf>,
+}
+
+/// Builds the rustls configuration, reading all certificates and keys up front.
+pub(crate) fn server_config(options: &TlsOptions) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = read_certs(&options.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&options.key_path).map_err(|err| {
+        anyhow!(
+            "failed to read private key {}: {err}",
+            options.key_path.display()
+        )
+    })?;
+
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+    let builder = match &options.client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid CA certificate in {}", path.display()))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("failed to set up client certificate verification")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("server certificate does not match the private key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| anyhow!("failed to read certificates {}: {err}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+    use tempfile::TempDir;
+
+    fn write_self_signed(dir: &TempDir, name: &str) -> (PathBuf, PathBuf) {
+        let CertifiedKey { cert, key_pair } =
+            generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.path().join(format!("{name}.crt"));
+        let key_path = dir.path().join(format!("{name}.key"));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn test_server_config() {
+        let dir = TempDir::new().unwrap();
+        let (cert_path, key_path) = write_self_signed(&dir, "server");
+
+        let config = server_config(&TlsOptions {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+        })
+        .unwrap();
+        assert_eq!(config.alpn_protocols[0], b"h2");
+    }
+
+    #[test]
+    fn test_server_config_with_client_ca() {
+        let dir = TempDir::new().unwrap();
+        let (cert_path, key_path) = write_self_signed(&dir, "server");
+        let (ca_path, _) = write_self_signed(&dir, "ca");
+
+        assert!(server_config(&TlsOptions {
+            cert_path,
+            key_path,
+            client_ca_path: Some(ca_path),
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn test_server_config_errors() {
+        let dir = TempDir::new().unwrap();
+        let (cert_path, key_path) = write_self_signed(&dir, "server");
+        let (_, other_key_path) = write_self_signed(&dir, "other");
+
+        // key belongs to a different certificate
+        assert!(server_config(&TlsOptions {
+            cert_path: cert_path.clone(),
+            key_path: other_key_path,
+            client_ca_path: None,
+        })
+        .is_err());
+
+        // certificate and key swapped
+        assert!(server_config(&TlsOptions {
+            cert_path: key_path,
+            key_path: cert_path,
+            client_ca_path: None,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Deserializer};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use crate::listener::Peer;
+
+/// Peers whose `Forwarded` and `X-Forwarded-*` headers are honored.
+///
+/// Defaults to loopback addresses and Unix domain sockets, i.e. a reverse proxy
+/// running on the same host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedProxies(Vec<TrustedProxy>);
+
+/// A single allowlist entry: an IP network in CIDR notation (a bare address
+/// matches only itself) or `unix` for every Unix domain socket connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrustedProxy {
+    Net { addr: IpAddr, prefix_len: u8 },
+    Unix,
+}
+
+impl TrustedProxies {
+    /// Trusts nobody; forwarded headers are always ignored.
+    pub fn none() -> Self {
+        Self(vec![])
+    }
+
+    pub(crate) fn contains(&self, peer: Peer) -> bool {
+        self.0.iter().any(|proxy| proxy.contains(peer))
+    }
+}
+
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        Self(vec![
+            TrustedProxy::Net {
+                addr: IpAddr::from([127, 0, 0, 0]),
+                prefix_len: 8,
+            },
+            TrustedProxy::Net {
+                addr: IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]),
+                prefix_len: 128,
+            },
+            TrustedProxy::Unix,
+        ])
+    }
+}
+
+impl TrustedProxy {
+    fn contains(&self, peer: Peer) -> bool {
+        match (self, peer) {
+            (Self::Unix, Peer::Unix) => true,
+            (Self::Net { addr, prefix_len }, Peer::Ip(ip)) => {
+                // IPv4 clients of a dual-stack socket show up as ::ffff:a.b.c.d
+                let (net, ip, bits) = match (addr, ip.to_canonical()) {
+                    (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                        (u32::from(*net).into(), u32::from(ip).into(), 32)
+                    }
+                    (IpAddr::V6(net), IpAddr::V6(ip)) => (u128::from(*net), u128::from(ip), 128),
+                    _ => return false,
+                };
+                (net ^ ip)
+                    .checked_shr(bits - u32::from(*prefix_len))
+                    .unwrap_or(0)
+                    == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "unix" {
+            return Ok(Self::Unix);
+        }
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| {
+            format!("invalid trusted proxy {s:?}: expected an IP address, CIDR or \"unix\"")
+        })?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in trusted proxy {s:?}"))?,
+            None => max_len,
+        };
+        Ok(Self::Net { addr, prefix_len })
+    }
+}
+
+/// Parses a comma-separated list; an empty string trusts nobody.
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TrustedProxies {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|entry| entry.parse())
+            .collect::<Result<_, _>>()
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Net { addr, prefix_len } => write!(f, "{addr}/{prefix_len}"),
+            Self::Unix => f.write_str("unix"),
+        }
+    }
+}
+
+impl fmt::Display for TrustedProxies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, proxy) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            proxy.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Peer {
+        Peer::Ip(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_default() {
+        let proxies = TrustedProxies::default();
+        assert!(proxies.contains(ip("127.0.0.1")));
+        assert!(proxies.contains(ip("127.10.0.1")));
+        assert!(proxies.contains(ip("::1")));
+        assert!(proxies.contains(ip("::ffff:127.0.0.1")));
+        assert!(proxies.contains(Peer::Unix));
+        assert!(!proxies.contains(ip("10.0.0.1")));
+        assert!(!proxies.contains(ip("::2")));
+        assert_eq!(proxies.to_string(), "127.0.0.0/8,::1/128,unix");
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let proxies: TrustedProxies = "10.0.0.0/8, 192.168.1.5, fd00::/8".parse().unwrap();
+        assert!(proxies.contains(ip("10.255.0.1")));
+        assert!(!proxies.contains(ip("11.0.0.1")));
+        assert!(proxies.contains(ip("192.168.1.5")));
+        assert!(!proxies.contains(ip("192.168.1.6")));
+        assert!(proxies.contains(ip("fd12::1")));
+        assert!(!proxies.contains(ip("fe80::1")));
+        assert!(!proxies.contains(ip("127.0.0.1")));
+        assert!(!proxies.contains(Peer::Unix));
+
+        let everyone: TrustedProxies = "0.0.0.0/0,::/0".parse().unwrap();
+        assert!(everyone.contains(ip("203.0.113.7")));
+        assert!(everyone.contains(ip("2001:db8::1")));
+
+        assert_eq!(
+            "".parse::<TrustedProxies>().unwrap(),
+            TrustedProxies::none()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("example.com".parse::<TrustedProxy>().is_err());
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("::/129".parse::<TrustedProxy>().is_err());
+        assert!("10.0.0.0/x".parse::<TrustedProxy>().is_err());
+    }
+}
@@ -1,107 +1,169 @@
-use axum::http::HeaderMap;
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+};
 use std::sync::Arc;
 use url::Url;
 
+use crate::{
+    forwarded::{for_nodes, parse_forwarded, trusted_hops, x_forwarded_for},
+    listener::Peer,
+    trusted_proxies::TrustedProxies,
+};
+
 #[derive(Debug, Clone)]
 pub struct ServerUrlResolver {
     fixed_url: Option<Url>,
+    tls: bool,
+    trusted_proxies: TrustedProxies,
+    base_path: String,
 }
 
 impl ServerUrlResolver {
     pub fn new(fixed_url: Option<Url>) -> Self {
-        Self { fixed_url }
+        Self {
+            fixed_url,
+            tls: false,
+            trusted_proxies: TrustedProxies::default(),
+            base_path: String::new(),
+        }
+    }
+
+    /// Marks the server as terminating TLS itself, so direct connections resolve to `https`.
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Sets the peers whose forwarded headers are honored.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Sets the path prefix the API is served under, as returned by [`normalize_base_path`].
+    pub fn with_base_path(mut self, base_path: String) -> Self {
+        self.base_path = base_path;
+        self
+    }
+
+    pub(crate) fn trusts(&self, peer: Peer) -> bool {
+        self.trusted_proxies.contains(peer)
+    }
+
+    pub(crate) fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
     }
 
     /// Resolves the server URL based on the following priority order:
-    /// 1. Fixed URL (if provided via --server_url option) - always takes precedence
-    /// 2. Forwarded header (RFC 7239) - parses "proto" and "host" directives from the FIRST entry
-    /// 3. X-Forwarded-* headers - uses FIRST values from X-Forwarded-Proto, X-Forwarded-Host, and optionally X-Forwarded-Port
-    /// 4. Host header - direct connection fallback, infers HTTPS for port 443, otherwise defaults to HTTP
+    /// 1. Fixed URL (if provided via --server_url option) - always takes precedence and is
+    ///    returned unchanged, so it must include the base path if one is configured
+    /// 2. Forwarded header (RFC 7239) - parses "proto" and "host" directives from one element
+    /// 3. X-Forwarded-* headers - uses one value each of X-Forwarded-Proto, X-Forwarded-Host,
+    ///    and optionally X-Forwarded-Port
+    /// 4. Host header - direct connection fallback, uses HTTPS when TLS is enabled or for port 443,
+    ///    otherwise defaults to HTTP
+    ///
+    /// When multiple proxies appended values (comma-separated), they are walked from the
+    /// right, skipping those about trusted proxies as listed in the `for` parameters or
+    /// X-Forwarded-For; the values the outermost trusted proxy added are used. Values
+    /// further left came from an untrusted client and may be forged.
     ///
-    /// When multiple proxy entries exist (comma-separated), we use the FIRST (leftmost) values
-    /// as they represent the original client request URL.
+    /// The path of the resolved URL is the X-Forwarded-Prefix (the part of the path a proxy
+    /// stripped before forwarding) followed by the configured base path, e.g.
+    /// `https://example.com/ingress/bonsai/`.
+    ///
+    /// Steps 2 and 3, the X-Forwarded-Proto hint in step 4 and X-Forwarded-Prefix only apply
+    /// when `peer` is a trusted proxy; any other client could otherwise point the returned
+    /// URLs at a host of its choosing.
     ///
     /// Returns ServerUrlError::UnableToResolve if no URL can be determined from any source.
-    pub fn resolve(&self, headers: &HeaderMap) -> Result<Url, ServerUrlError> {
+    pub(crate) fn resolve(&self, headers: &HeaderMap, peer: Peer) -> Result<Url, ServerUrlError> {
         if let Some(ref url) = self.fixed_url {
             return Ok(url.clone());
         }
 
-        if let Some(url) = self.extract_from_forwarded_header(headers) {
-            return Ok(url);
+        let trusted = self.trusts(peer);
+        let mut url = if trusted {
+            self.extract_from_forwarded_header(headers)
+                .or_else(|| self.extract_from_x_forwarded_headers(headers))
+                .or_else(|| self.extract_from_host_header(headers))
+        } else {
+            let mut direct = HeaderMap::new();
+            if let Some(host) = headers.get(header::HOST) {
+                direct.insert(header::HOST, host.clone());
+            }
+            self.extract_from_host_header(&direct)
         }
+        .ok_or(ServerUrlError::UnableToResolve)?;
 
-        if let Some(url) = self.extract_from_x_forwarded_headers(headers) {
-            return Ok(url);
-        }
+        let forwarded_prefix = if trusted {
+            self.extract_forwarded_prefix(headers)
+        } else {
+            None
+        };
+        url.set_path(&format!(
+            "{}{}/",
+            forwarded_prefix.unwrap_or_default(),
+            self.base_path
+        ));
+        Ok(url)
+    }
 
-        if let Some(url) = self.extract_from_host_header(headers) {
-            return Ok(url);
-        }
+    /// Returns the value of an X-Forwarded-* header added by the outermost trusted proxy:
+    /// as many values from the right as X-Forwarded-For ends in trusted proxies.
+    fn x_forwarded_value<'a>(&self, headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        let values: Vec<&str> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect();
+        let skipped =
+            x_forwarded_for(headers).map_or(0, |hops| trusted_hops(&hops, &self.trusted_proxies));
+        values.iter().rev().nth(skipped).or(values.first()).copied()
+    }
 
-        Err(ServerUrlError::UnableToResolve)
+    /// Returns the X-Forwarded-Prefix value without its trailing slash, if it is a path.
+    ///
+    /// Repeated slashes are collapsed: a path starting with `//` would make the generated
+    /// URLs protocol-relative references to another host once resolved by a client.
+    pub(crate) fn extract_forwarded_prefix(&self, headers: &HeaderMap) -> Option<String> {
+        let prefix = self
+            .x_forwarded_value(headers, "x-forwarded-prefix")
+            .filter(|s| s.starts_with('/'))?;
+        let segments: Vec<&str> = prefix.split('/').filter(|s| !s.is_empty()).collect();
+        (!segments.is_empty()).then(|| format!("/{}", segments.join("/")))
     }
 
     pub(crate) fn extract_from_forwarded_header(&self, headers: &HeaderMap) -> Option<Url> {
-        headers.get("forwarded").and_then(|value| {
-            value.to_str().ok().and_then(|s| {
-                // RFC 7239: Each proxy appends its own entry, separated by commas
-                // Example: "proto=https;host=original.com, proto=http;host=proxy1.com, proto=https;host=proxy2.com"
-                //          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
-                //          Client's original request (what we want to extract)
-
-                // Split by comma to get individual proxy entries and take the first one
-                if let Some(first_entry) = s.split(',').next() {
-                    let mut proto = None;
-                    let mut host = None;
-
-                    // Parse each directive in the first entry
-                    for directive in first_entry.split(';') {
-                        let trimmed = directive.trim();
-
-                        if let Some(p) = trimmed.strip_prefix("proto=") {
-                            proto = Some(p.trim_matches('"'));
-                        } else if let Some(h) = trimmed.strip_prefix("host=") {
-                            host = Some(h.trim_matches('"'));
-                        }
-                    }
-
-                    // Only build URL if we have both proto and host from the same proxy entry
-                    // This ensures consistency - both values come from the same proxy
-                    if let (Some(proto), Some(host)) = (proto, host) {
-                        return Url::parse(&format!("{}://{}", proto, host)).ok();
-                    }
-                }
-                None
-            })
-        })
+        // RFC 7239: Each proxy appends its own element, separated by commas
+        // Example: "for=198.51.100.1;proto=https;host=forged.com, for=192.0.2.7;proto=https;host=example.com, for=10.0.0.2;proto=http;host=internal"
+        //                                                         ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+        // With 10.0.0.0/8 trusted, the element whose `for` is the first untrusted node from the
+        // right describes the request as the client sent it to our edge proxy
+        let elements = parse_forwarded(headers)?;
+        let skipped = trusted_hops(&for_nodes(&elements), &self.trusted_proxies);
+        let element = &elements[elements.len() - 1 - skipped];
+
+        // Only build URL if we have both proto and host from the same proxy entry
+        // This ensures consistency - both values come from the same proxy
+        let (proto, host) = (element.proto.as_ref()?, element.host.as_ref()?);
+        Url::parse(&format!("{}://{}", proto, host)).ok()
     }
 
     pub(crate) fn extract_from_x_forwarded_headers(&self, headers: &HeaderMap) -> Option<Url> {
         // X-Forwarded-* headers: Each proxy appends its value, creating comma-separated lists
-        // Example: X-Forwarded-Host: "original.com, proxy1.com, proxy2.com"
-        //                             ^^^^^^^^^^^^
-        //                             Client's original host (what we extract)
-        // We take the FIRST value (leftmost) from each header
-
-        let proto = headers
-            .get("x-forwarded-proto")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.split(',').next())
-            .map(|s| s.trim())
+        // Example: X-Forwarded-Host: "forged.com, example.com, internal"
+        //          X-Forwarded-For:  "198.51.100.1, 192.0.2.7, 10.0.0.2"
+        // With 10.0.0.0/8 trusted, "example.com" is what the edge proxy received
+        let proto = self
+            .x_forwarded_value(headers, "x-forwarded-proto")
             .unwrap_or("http");
-
-        let host = headers
-            .get("x-forwarded-host")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.split(',').next())
-            .map(|s| s.trim())?;
-
-        let port = headers
-            .get("x-forwarded-port")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.split(',').next())
-            .map(|s| s.trim());
+        let host = self.x_forwarded_value(headers, "x-forwarded-host")?;
+        let port = self.x_forwarded_value(headers, "x-forwarded-port");
 
         let url_string = if let Some(port) = port {
             format!("{}://{}:{}", proto, host, port)
@@ -116,18 +178,12 @@ impl ServerUrlResolver {
         headers.get("host").and_then(|value| {
             value.to_str().ok().and_then(|host| {
                 // Infer scheme for direct connections (no proxy headers):
-                // - Port 443 implies HTTPS
+                // - Native TLS or port 443 implies HTTPS
                 // - Check X-Forwarded-Proto as a hint (though this is unusual for direct connections)
                 // - Default to HTTP for all other cases
-                let scheme = if host.ends_with(":443") {
+                let scheme = if self.tls || host.ends_with(":443") {
                     "https"
-                } else if headers
-                    .get("x-forwarded-proto")
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.split(',').next())
-                    .map(|s| s.trim())
-                    == Some("https")
-                {
+                } else if self.x_forwarded_value(headers, "x-forwarded-proto") == Some("https") {
                     "https"
                 } else {
                     "http"
@@ -147,10 +203,48 @@ pub enum ServerUrlError {
 
 pub type SharedUrlResolver = Arc<ServerUrlResolver>;
 
+/// Normalizes a configured base path to `/segment[/segment...]` without a trailing
+/// slash; an empty path or `/` means the API is served at the root.
+pub(crate) fn normalize_base_path(path: &str) -> Result<String, String> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    let valid = trimmed.starts_with('/')
+        && !trimmed.contains("//")
+        && !trimmed.contains(['?', '#', ':', '*', '{', '}']);
+    if valid {
+        Ok(trimmed.to_string())
+    } else {
+        Err(format!(
+            "base_path must be a path such as /bonsai, got {path:?}"
+        ))
+    }
+}
+
+/// HTTP/2 clients send the host as the `:authority` pseudo-header instead of `Host`;
+/// copy it over so the host header fallback also works for them.
+pub(crate) async fn fill_host_header(mut request: Request) -> Request {
+    if !request.headers().contains_key(header::HOST) {
+        let authority = request
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok());
+        if let Some(authority) = authority {
+            request.headers_mut().insert(header::HOST, authority);
+        }
+    }
+    request
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use axum::http::{HeaderMap, HeaderValue};
+
+    const PROXY: Peer = Peer::Ip(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST));
+    const CLIENT: Peer = Peer::Ip(std::net::IpAddr::V4(std::net::Ipv4Addr::new(
+        203, 0, 113, 7,
+    )));
 
     #[test]
     fn test_extract_from_forwarded_header_single_entry() {
@@ -171,12 +265,21 @@ mod tests {
         let resolver = ServerUrlResolver::new(None);
         let mut headers = HeaderMap::new();
 
-        // Multiple proxy entries - should use the first (leftmost) one
+        // Multiple proxy entries without `for` - only the nearest proxy's can be trusted
         headers.insert(
             "forwarded",
             HeaderValue::from_static("proto=https;host=original.com, proto=http;host=proxy1.com, proto=https;host=proxy2.com"),
         );
 
+        let url = resolver.extract_from_forwarded_header(&headers).unwrap();
+        assert_eq!(url.as_str(), "https://proxy2.com/");
+
+        // entries about trusted proxies are skipped up to the client's
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("for=198.51.100.1;proto=https;host=original.com, for=127.0.0.2;proto=http;host=proxy1.com, for=127.0.0.3;proto=https;host=proxy2.com"),
+        );
+
         let url = resolver.extract_from_forwarded_header(&headers).unwrap();
         assert_eq!(url.as_str(), "https://original.com/");
     }
@@ -195,6 +298,29 @@ mod tests {
         assert_eq!(url.as_str(), "https://example.com/");
     }
 
+    #[test]
+    fn test_extract_from_forwarded_header_ipv6_and_separators() {
+        let resolver = ServerUrlResolver::new(None);
+        let mut headers = HeaderMap::new();
+
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static(
+                r#"for=192.0.2.1, for="[2001:db8::1]:4711";Host="[::1]:8080";PROTO=https"#,
+            ),
+        );
+
+        let url = resolver.extract_from_forwarded_header(&headers).unwrap();
+        assert_eq!(url.as_str(), "https://[::1]:8080/");
+
+        // a malformed header is ignored entirely
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("proto=https;host=example.com;host=evil.com"),
+        );
+        assert!(resolver.extract_from_forwarded_header(&headers).is_none());
+    }
+
     #[test]
     fn test_extract_from_forwarded_header_missing_proto() {
         let resolver = ServerUrlResolver::new(None);
@@ -245,7 +371,11 @@ mod tests {
         let resolver = ServerUrlResolver::new(None);
         let mut headers = HeaderMap::new();
 
-        // Multiple values - should use the first (leftmost) one
+        // Multiple values, all added by trusted proxies - should use the first (leftmost) one
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 127.0.0.2, 127.0.0.3"),
+        );
         headers.insert(
             "x-forwarded-proto",
             HeaderValue::from_static("https, http, https"),
@@ -310,6 +440,17 @@ mod tests {
         assert_eq!(url.as_str(), "http://example.com:8080/");
     }
 
+    #[test]
+    fn test_extract_from_host_header_with_tls() {
+        let resolver = ServerUrlResolver::new(None).with_tls(true);
+        let mut headers = HeaderMap::new();
+
+        headers.insert("host", HeaderValue::from_static("example.com:8443"));
+
+        let url = resolver.extract_from_host_header(&headers).unwrap();
+        assert_eq!(url.as_str(), "https://example.com:8443/");
+    }
+
     #[test]
     fn test_extract_from_host_header_https_port_443() {
         let resolver = ServerUrlResolver::new(None);
@@ -360,7 +501,7 @@ mod tests {
         headers.insert("host", HeaderValue::from_static("host.com"));
 
         // Fixed URL should always take precedence
-        let url = resolver.resolve(&headers).unwrap();
+        let url = resolver.resolve(&headers, PROXY).unwrap();
         assert_eq!(url, fixed_url);
     }
 
@@ -381,7 +522,7 @@ mod tests {
         headers.insert("host", HeaderValue::from_static("host.com"));
 
         // Forwarded header should take precedence
-        let url = resolver.resolve(&headers).unwrap();
+        let url = resolver.resolve(&headers, PROXY).unwrap();
         assert_eq!(url.as_str(), "https://forwarded.com/");
     }
 
@@ -398,7 +539,7 @@ mod tests {
         headers.insert("host", HeaderValue::from_static("host.com"));
 
         // X-Forwarded headers should take precedence over Host
-        let url = resolver.resolve(&headers).unwrap();
+        let url = resolver.resolve(&headers, PROXY).unwrap();
         assert_eq!(url.as_str(), "https://xforwarded.com/");
     }
 
@@ -410,19 +551,156 @@ mod tests {
         headers.insert("host", HeaderValue::from_static("host.com"));
 
         // Should fall back to Host header
-        let url = resolver.resolve(&headers).unwrap();
+        let url = resolver.resolve(&headers, PROXY).unwrap();
+        assert_eq!(url.as_str(), "http://host.com/");
+    }
+
+    #[test]
+    fn test_resolve_ignores_forwarded_headers_from_untrusted_peer() {
+        let resolver = ServerUrlResolver::new(None);
+        let mut headers = HeaderMap::new();
+
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("proto=https;host=attacker.com"),
+        );
+        headers.insert("x-forwarded-host", HeaderValue::from_static("attacker.com"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        headers.insert("host", HeaderValue::from_static("host.com"));
+
+        let url = resolver.resolve(&headers, CLIENT).unwrap();
+        assert_eq!(url.as_str(), "http://host.com/");
+
+        // unless the peer is explicitly trusted
+        let resolver =
+            ServerUrlResolver::new(None).with_trusted_proxies("203.0.113.0/24".parse().unwrap());
+        let url = resolver.resolve(&headers, CLIENT).unwrap();
+        assert_eq!(url.as_str(), "https://attacker.com/");
+        let url = resolver.resolve(&headers, PROXY).unwrap();
         assert_eq!(url.as_str(), "http://host.com/");
     }
 
+    #[test]
+    fn test_resolve_ignores_values_forged_before_trusted_proxy() {
+        let resolver =
+            ServerUrlResolver::new(None).with_trusted_proxies("10.0.0.0/8".parse().unwrap());
+        let proxy = Peer::Ip("10.0.0.1".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("internal"));
+
+        // the client sent its own Forwarded header, which the edge proxy appended to
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static(
+                "for=198.51.100.1;proto=https;host=attacker.com, \
+                 for=192.0.2.7;proto=https;host=example.com, for=10.0.0.2;proto=http;host=internal",
+            ),
+        );
+        let url = resolver.resolve(&headers, proxy).unwrap();
+        assert_eq!(url.as_str(), "https://example.com/");
+
+        headers.remove("forwarded");
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 192.0.2.7, 10.0.0.2"),
+        );
+        headers.insert(
+            "x-forwarded-host",
+            HeaderValue::from_static("attacker.com, example.com, internal"),
+        );
+        headers.insert(
+            "x-forwarded-proto",
+            HeaderValue::from_static("https, https, http"),
+        );
+        headers.insert(
+            "x-forwarded-prefix",
+            HeaderValue::from_static("/evil, /ingress, /"),
+        );
+        let url = resolver.resolve(&headers, proxy).unwrap();
+        assert_eq!(url.as_str(), "https://example.com/ingress/");
+    }
+
     #[test]
     fn test_resolve_no_headers_returns_error() {
         let resolver = ServerUrlResolver::new(None);
         let headers = HeaderMap::new();
 
-        let result = resolver.resolve(&headers);
+        let result = resolver.resolve(&headers, PROXY);
         assert!(matches!(result, Err(ServerUrlError::UnableToResolve)));
     }
 
+    #[test]
+    fn test_resolve_with_prefixes() {
+        let resolver = ServerUrlResolver::new(None).with_base_path("/bonsai".to_string());
+        let mut headers = HeaderMap::new();
+
+        headers.insert("host", HeaderValue::from_static("host.com"));
+        let url = resolver.resolve(&headers, PROXY).unwrap();
+        assert_eq!(url.as_str(), "http://host.com/bonsai/");
+
+        // the proxy stripped /ingress before forwarding to /bonsai; without X-Forwarded-For
+        // only the value the nearest proxy appended is used
+        headers.insert(
+            "x-forwarded-prefix",
+            HeaderValue::from_static("/other, /ingress/"),
+        );
+        let url = resolver.resolve(&headers, PROXY).unwrap();
+        assert_eq!(url.as_str(), "http://host.com/ingress/bonsai/");
+
+        // untrusted clients cannot add a prefix
+        let url = resolver.resolve(&headers, CLIENT).unwrap();
+        assert_eq!(url.as_str(), "http://host.com/bonsai/");
+
+        // a fixed URL is returned as configured
+        let fixed_url = Url::parse("https://fixed.com/api/").unwrap();
+        let resolver =
+            ServerUrlResolver::new(Some(fixed_url.clone())).with_base_path("/bonsai".to_string());
+        assert_eq!(resolver.resolve(&headers, PROXY).unwrap(), fixed_url);
+    }
+
+    #[test]
+    fn test_extract_forwarded_prefix() {
+        let resolver = ServerUrlResolver::new(None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(resolver.extract_forwarded_prefix(&headers), None);
+
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static("/"));
+        assert_eq!(resolver.extract_forwarded_prefix(&headers), None);
+
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static("evil.com"));
+        assert_eq!(resolver.extract_forwarded_prefix(&headers), None);
+
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static(" /a/b/ "));
+        assert_eq!(
+            resolver.extract_forwarded_prefix(&headers).as_deref(),
+            Some("/a/b")
+        );
+
+        headers.insert(
+            "x-forwarded-prefix",
+            HeaderValue::from_static("//evil.example//a"),
+        );
+        assert_eq!(
+            resolver.extract_forwarded_prefix(&headers).as_deref(),
+            Some("/evil.example/a")
+        );
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static("//"));
+        assert_eq!(resolver.extract_forwarded_prefix(&headers), None);
+    }
+
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize_base_path("").unwrap(), "");
+        assert_eq!(normalize_base_path("/").unwrap(), "");
+        assert_eq!(normalize_base_path("/bonsai/").unwrap(), "/bonsai");
+        assert_eq!(normalize_base_path("/a/b").unwrap(), "/a/b");
+        assert!(normalize_base_path("bonsai").is_err());
+        assert!(normalize_base_path("/a//b").is_err());
+        assert!(normalize_base_path("//evil.example").is_err());
+        assert!(normalize_base_path("/:id").is_err());
+        assert!(normalize_base_path("/a?b").is_err());
+    }
+
     #[test]
     fn test_extract_from_x_forwarded_headers_with_spaces() {
         let resolver = ServerUrlResolver::new(None);
@@ -438,6 +716,10 @@ mod tests {
             HeaderValue::from_static(" example.com , other.com"),
         );
         headers.insert("x-forwarded-port", HeaderValue::from_static(" 8443 , 80"));
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static(" 198.51.100.1 , 127.0.0.2"),
+        );
 
         let url = resolver.extract_from_x_forwarded_headers(&headers).unwrap();
         assert_eq!(url.as_str(), "https://example.com:8443/");
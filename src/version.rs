@@ -1,5 +1,98 @@
 use anyhow::{anyhow, Context, Result};
-use std::process::Command;
+use axum::{extract::Request, middleware::Next, response::Response, Extension};
+use std::{process::Command, sync::Arc};
+
+use crate::{error::Error, r0vm::R0vm};
+
+/// Header the Bonsai SDK sends its risc0-zkvm version in.
+pub(crate) const VERSION_HEADER: &str = "x-risc0-version";
+
+/// risc0-zkvm versions the server proves with, reported by `GET /version`: the one
+/// it is built with, plus those of the [`R0vm`] binaries it was given.
+///
+/// Clients are compatible if their version has the same major and minor version
+/// as one of these; receipts are not guaranteed to deserialize across minor versions.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SupportedVersions {
+    r0vms: Arc<Vec<R0vm>>,
+}
+
+impl SupportedVersions {
+    /// Versions compatible with the built-in one are proven in-process, so their
+    /// r0vm binaries are left out.
+    pub(crate) fn new(r0vms: Vec<R0vm>) -> Self {
+        let r0vms = r0vms
+            .into_iter()
+            .filter(|r0vm| !compatible(&r0vm.version, risc0_zkvm::VERSION))
+            .collect();
+        Self {
+            r0vms: Arc::new(r0vms),
+        }
+    }
+
+    pub(crate) fn list(&self) -> Vec<String> {
+        std::iter::once(risc0_zkvm::VERSION)
+            .chain(self.r0vms.iter().map(|r0vm| r0vm.version.as_str()))
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub(crate) fn is_compatible(&self, client_version: &str) -> bool {
+        self.list()
+            .iter()
+            .any(|version| compatible(client_version, version))
+    }
+
+    /// Returns the r0vm to prove with for a client of `client_version`, or `None` if
+    /// it is proven in-process: the client is compatible with the built-in version,
+    /// or did not send its version.
+    pub(crate) fn r0vm_for(&self, client_version: Option<&str>) -> Option<R0vm> {
+        let client_version = client_version?;
+        if compatible(client_version, risc0_zkvm::VERSION) {
+            return None;
+        }
+        // the newest patch release of the client's minor version
+        self.r0vms
+            .iter()
+            .rev()
+            .find(|r0vm| compatible(client_version, &r0vm.version))
+            .cloned()
+    }
+}
+
+fn compatible(a: &str, b: &str) -> bool {
+    major_minor(a).is_some_and(|a| major_minor(b) == Some(a))
+}
+
+fn major_minor(version: &str) -> Option<(&str, &str)> {
+    let mut parts = version.trim().trim_start_matches('v').split('.');
+    let major = parts.next().filter(|major| !major.is_empty())?;
+    let minor = parts.next().filter(|minor| !minor.is_empty())?;
+    Some((major, minor))
+}
+
+/// Middleware rejecting clients whose `x-risc0-version` is incompatible with every
+/// supported version, before they upload data the server cannot prove for them.
+///
+/// Requests without the header, e.g. from curl, and `GET /version` are let through.
+pub(crate) async fn check_client_version(
+    Extension(versions): Extension<SupportedVersions>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if request.uri().path() != "/version" {
+        if let Some(client_version) = request.headers().get(VERSION_HEADER) {
+            let client_version = String::from_utf8_lossy(client_version.as_bytes());
+            if !versions.is_compatible(&client_version) {
+                return Err(Error::UnsupportedVersion(
+                    client_version.into_owned(),
+                    versions.list(),
+                ));
+            }
+        }
+    }
+    Ok(next.run(request).await)
+}
 
 pub fn check_docker() -> Result<()> {
     // Check if docker command exists
@@ -110,6 +203,37 @@ mod tests {
         assert_eq!(extract_version("  1.2.3  ").unwrap(), "1.2.3");
     }
 
+    #[test]
+    fn test_compatible() {
+        assert!(compatible("3.0.3", "3.0.0"));
+        assert!(compatible("3.0.0-rc.1", "3.0.3"));
+        assert!(!compatible("3.1.0", "3.0.3"));
+        assert!(!compatible("2.0.0", "3.0.3"));
+        assert!(!compatible("3", "3.0.3"));
+        assert!(!compatible("", "3.0.3"));
+    }
+
+    #[test]
+    fn test_supported_versions() {
+        let r0vm = |version: &str| R0vm {
+            version: version.to_string(),
+            path: format!("/opt/r0vm-{version}").into(),
+        };
+        let builtin = risc0_zkvm::VERSION;
+        let versions = SupportedVersions::new(vec![r0vm("1.2.0"), r0vm("1.2.1"), r0vm(builtin)]);
+        assert_eq!(versions.list(), [builtin, "1.2.0", "1.2.1"]);
+
+        assert!(versions.is_compatible(builtin));
+        assert_eq!(versions.r0vm_for(Some(builtin)), None);
+        assert_eq!(versions.r0vm_for(None), None);
+        assert!(versions.is_compatible("1.2.0"));
+        assert_eq!(versions.r0vm_for(Some("1.2.0")), Some(r0vm("1.2.1")));
+        assert!(!versions.is_compatible("1.1.0"));
+        assert_eq!(versions.r0vm_for(Some("1.1.0")), None);
+
+        assert_eq!(SupportedVersions::default().list(), [builtin]);
+    }
+
     #[test]
     fn test_version_matches() {
         assert!(version_matches("1.0.0", "1.0").unwrap());